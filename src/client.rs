@@ -1,4 +1,8 @@
+use futures::future::BoxFuture;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Package {
@@ -8,16 +12,144 @@ pub struct Package {
     pub version: String,
 }
 
+pub const DEFAULT_REGISTRY_URL: &str = "https://package.elm-lang.org";
+
+/// Published Elm packages are GitHub repositories tagged with each version.
+pub const DEFAULT_SOURCE_URL: &str = "https://raw.githubusercontent.com";
+
+pub const DEFAULT_RELEASES_TTL: Duration = Duration::from_secs(15 * 60);
+
+pub const DEFAULT_REQUESTS_PER_SECOND: u32 = 10;
+
+/// Each published version mapped to its unix timestamp, as in releases.json.
+pub type Releases = HashMap<String, u32>;
+
+type ReleasesCache = HashMap<(String, String), (Instant, Releases)>;
+
+pub trait PackageRegistry: Send + Sync {
+    fn get_releases<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Releases>>;
+
+    fn get_latest_package_version<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<String>>;
+
+    fn get_docs<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+        version: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<serde_json::Value>>;
+
+    fn fetch_all_packages(&self) -> BoxFuture<'_, anyhow::Result<Vec<Package>>>;
+
+    fn get_elm_json<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+        version: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<serde_json::Value>>;
+
+    fn get_package_source<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+        version: &'a str,
+        module: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<String>>;
+
+    /// The package's README.md, None if it has none
+    fn get_readme<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+        version: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>>;
+
+    fn package_url(&self, name: &str, version: &str) -> String;
+}
+
 #[derive(Clone)]
 pub struct ElmClient {
     client: reqwest::Client,
+    base_url: String,
+    source_url: String,
+    releases: Arc<Mutex<ReleasesCache>>,
+    releases_ttl: Duration,
+    limiter: Arc<RateLimiter>,
+}
+
+impl Default for ElmClient {
+    fn default() -> Self {
+        Self::new(DEFAULT_REGISTRY_URL)
+    }
 }
 
 impl ElmClient {
-    pub fn new() -> Self {
+    pub fn new(base_url: &str) -> Self {
         Self {
             client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            source_url: DEFAULT_SOURCE_URL.to_string(),
+            releases: Default::default(),
+            releases_ttl: DEFAULT_RELEASES_TTL,
+            limiter: Arc::new(RateLimiter::new(DEFAULT_REQUESTS_PER_SECOND)),
+        }
+    }
+
+    /// Where package sources are fetched from, laid out as
+    /// `{source_url}/{username}/{package}/{version}/src/...`
+    pub fn with_source_url(mut self, source_url: &str) -> Self {
+        self.source_url = source_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_releases_ttl(mut self, ttl: Duration) -> Self {
+        self.releases_ttl = ttl;
+        self
+    }
+
+    /// Caps outbound requests, shared by every clone of this client.
+    pub fn with_requests_per_second(mut self, requests_per_second: u32) -> Self {
+        self.limiter = Arc::new(RateLimiter::new(requests_per_second));
+        self
+    }
+
+    /// Every registry and source fetch goes through here so the rate limit applies.
+    async fn send(&self, url: impl reqwest::IntoUrl) -> reqwest::Result<reqwest::Response> {
+        self.limiter.wait().await;
+        self.client.get(url).send().await
+    }
+
+    pub async fn get_releases(&self, username: &str, package: &str) -> anyhow::Result<Releases> {
+        let key = (username.to_string(), package.to_string());
+        if let Some((fetched_at, releases)) = self.releases.lock().await.get(&key)
+            && fetched_at.elapsed() < self.releases_ttl
+        {
+            return Ok(releases.clone());
         }
+
+        let releases: Releases = self
+            .get_json(
+                &format!(
+                    "{}/packages/{}/{}/releases.json",
+                    self.base_url, username, package
+                ),
+                "PACKAGE",
+                Some(&format!("{}/{}", username, package)),
+            )
+            .await?;
+
+        self.releases
+            .lock()
+            .await
+            .insert(key, (Instant::now(), releases.clone()));
+        Ok(releases)
     }
 
     pub async fn get_latest_package_version(
@@ -25,18 +157,7 @@ impl ElmClient {
         username: &str,
         package: &str,
     ) -> anyhow::Result<String> {
-        let releases: HashMap<String, u32> = self
-            .client
-            .get(format!(
-                "https://package.elm-lang.org/packages/{}/{}/releases.json",
-                username, package
-            ))
-            .send()
-            .await
-            .map_err(fail("PACKAGE_FETCH_FAIL"))?
-            .json()
-            .await
-            .map_err(fail("PACKAGE_DECODE_FAIL"))?;
+        let releases = self.get_releases(username, package).await?;
 
         releases
             .iter()
@@ -51,40 +172,246 @@ impl ElmClient {
         package: &str,
         version: &str,
     ) -> anyhow::Result<serde_json::Value> {
+        self.get_json(
+            &format!(
+                "{}/packages/{}/{}/{}/docs.json",
+                self.base_url, username, package, version
+            ),
+            "DOCS",
+            Some(&format!("{}/{} {}", username, package, version)),
+        )
+        .await
+    }
+
+    pub async fn get_elm_json(
+        &self,
+        username: &str,
+        package: &str,
+        version: &str,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.get_json(
+            &format!(
+                "{}/packages/{}/{}/{}/elm.json",
+                self.base_url, username, package, version
+            ),
+            "ELM_JSON",
+            Some(&format!("{}/{} {}", username, package, version)),
+        )
+        .await
+    }
+
+    pub async fn get_package_source(
+        &self,
+        username: &str,
+        package: &str,
+        version: &str,
+        module: &str,
+    ) -> anyhow::Result<String> {
+        let path = module.replace('.', "/");
         let res = self
-            .client
-            .get(format!(
-                "https://package.elm-lang.org/packages/{}/{}/{}/docs.json",
-                username, package, version
+            .send(format!(
+                "{}/{}/{}/{}/src/{}.elm",
+                self.source_url, username, package, version, path
             ))
-            .send()
             .await
-            .map_err(fail("DOCS_FETCH_FAIL"))?
-            .json()
+            .map_err(fail("SOURCE_FETCH_FAIL"))?;
+
+        let status = res.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow::anyhow!(
+                "SOURCE_NOT_FOUND: {module} in {username}/{package} {version}"
+            ));
+        }
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("SOURCE_FETCH_FAIL: HTTP {status}"));
+        }
+
+        res.text().await.map_err(fail("SOURCE_DECODE_FAIL"))
+    }
+
+    pub async fn get_readme(
+        &self,
+        username: &str,
+        package: &str,
+        version: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let res = self
+            .send(format!(
+                "{}/packages/{}/{}/{}/README.md",
+                self.base_url, username, package, version
+            ))
             .await
-            .map_err(fail("DOCS_DECODE_FAIL"))?;
+            .map_err(fail("README_FETCH_FAIL"))?;
+
+        let status = res.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("README_FETCH_FAIL: HTTP {status}"));
+        }
 
-        Ok(res)
+        res.text()
+            .await
+            .map(Some)
+            .map_err(fail("README_DECODE_FAIL"))
     }
 
     pub async fn fetch_all_packages(&self) -> anyhow::Result<Vec<Package>> {
+        self.get_json(&format!("{}/search.json", self.base_url), "PACKAGES", None)
+            .await
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        tag: &str,
+        coordinate: Option<&str>,
+    ) -> anyhow::Result<T> {
         let res = self
-            .client
-            .get("https://package.elm-lang.org/search.json")
-            .send()
+            .send(url)
             .await
-            .map_err(fail("PACKAGES_FETCH_FAIL"))?
-            .json()
+            .map_err(fail(&format!("{tag}_FETCH_FAIL")))?;
+
+        let status = res.status();
+        if let (reqwest::StatusCode::NOT_FOUND, Some(coordinate)) = (status, coordinate) {
+            return Err(anyhow::anyhow!("PACKAGE_NOT_FOUND: {coordinate}"));
+        }
+        let content_type = res
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        if let Some(content_type) = content_type
+            && !content_type.contains("json")
+        {
+            let body = res.text().await.unwrap_or_default();
+            let snippet: String = body.chars().take(200).collect();
+            return Err(anyhow::anyhow!(
+                "REGISTRY_UNAVAILABLE: HTTP {status}, {content_type}: {snippet}"
+            ));
+        }
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("{tag}_FETCH_FAIL: HTTP {status}"));
+        }
+
+        res.json()
             .await
-            .map_err(fail("PACKAGES_DECODE_FAIL"))?;
+            .map_err(fail(&format!("{tag}_DECODE_FAIL")))
+    }
+}
+
+impl PackageRegistry for ElmClient {
+    fn get_releases<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Releases>> {
+        Box::pin(ElmClient::get_releases(self, username, package))
+    }
 
-        Ok(res)
+    fn get_latest_package_version<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(ElmClient::get_latest_package_version(
+            self, username, package,
+        ))
+    }
+
+    fn get_docs<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+        version: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<serde_json::Value>> {
+        Box::pin(ElmClient::get_docs(self, username, package, version))
+    }
+
+    fn fetch_all_packages(&self) -> BoxFuture<'_, anyhow::Result<Vec<Package>>> {
+        Box::pin(ElmClient::fetch_all_packages(self))
+    }
+
+    fn get_elm_json<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+        version: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<serde_json::Value>> {
+        Box::pin(ElmClient::get_elm_json(self, username, package, version))
+    }
+
+    fn get_package_source<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+        version: &'a str,
+        module: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(ElmClient::get_package_source(
+            self, username, package, version, module,
+        ))
+    }
+
+    fn get_readme<'a>(
+        &'a self,
+        username: &'a str,
+        package: &'a str,
+        version: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        Box::pin(ElmClient::get_readme(self, username, package, version))
+    }
+
+    fn package_url(&self, name: &str, version: &str) -> String {
+        format!("{}/packages/{}/{}/", self.base_url, name, version)
+    }
+}
+
+/// Spaces requests evenly at `requests_per_second`, each caller reserving the
+/// next free slot and sleeping until it arrives.
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / requests_per_second.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
     }
 }
 
 fn fail<E: std::fmt::Debug>(tag: &str) -> impl Fn(E) -> anyhow::Error {
     move |err: E| {
-        eprintln!("{}:\n{:#?}", tag, err);
+        tracing::error!(error = ?err, "{}", tag);
         anyhow::anyhow!("{tag}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::new(50);
+        let started = Instant::now();
+        for _ in 0..4 {
+            limiter.wait().await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(60));
+    }
+}
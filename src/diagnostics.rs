@@ -0,0 +1,207 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// One problem from `elm make --report=json`, with its message flattened to text.
+#[derive(Clone, serde::Serialize)]
+pub struct Problem {
+    pub path: Option<String>,
+    pub title: String,
+    pub region: Option<serde_json::Value>,
+    pub message: String,
+}
+
+/// Reports are either `compile-errors` (per-file problems) or a single general `error`.
+pub fn problems(report: &serde_json::Value) -> Vec<Problem> {
+    match report["type"].as_str() {
+        Some("compile-errors") => report["errors"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|error| {
+                let path = error["path"].as_str().map(|p| p.to_string());
+                error["problems"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(move |problem| Problem {
+                        path: path.clone(),
+                        title: problem["title"].as_str().unwrap_or_default().to_string(),
+                        region: Some(problem["region"].clone()),
+                        message: message_text(&problem["message"]),
+                    })
+            })
+            .collect(),
+        _ => vec![Problem {
+            path: report["path"].as_str().map(|p| p.to_string()),
+            title: report["title"].as_str().unwrap_or_default().to_string(),
+            region: None,
+            message: message_text(&report["message"]),
+        }],
+    }
+}
+
+/// Messages are a list of plain strings and styled `{ "string": ... }` chunks.
+pub fn message_text(message: &serde_json::Value) -> String {
+    message
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|chunk| chunk.as_str().or_else(|| chunk["string"].as_str()))
+        .collect()
+}
+
+const SNIPPET_CONTEXT: usize = 2;
+
+/// Adds a `source` excerpt to every problem in a `compile-errors` report whose
+/// file and region can be read, or `{ "available": false }` when they can't.
+pub fn add_source_snippets(report: &mut serde_json::Value, project_folder: &str) {
+    let Some(errors) = report["errors"].as_array_mut() else {
+        return;
+    };
+    for error in errors {
+        // relative paths are relative to where elm make ran
+        let path = error["path"]
+            .as_str()
+            .map(|path| std::path::Path::new(project_folder).join(path));
+        let contents = path.and_then(|path| std::fs::read_to_string(path).ok());
+        let Some(problems) = error["problems"].as_array_mut() else {
+            continue;
+        };
+        for problem in problems {
+            let start = problem["region"]["start"]["line"].as_u64();
+            let end = problem["region"]["end"]["line"].as_u64();
+            let snippet = match (&contents, start, end) {
+                (Some(contents), Some(start), Some(end)) => {
+                    snippet(contents, start as usize, end as usize)
+                }
+                _ => None,
+            };
+            problem["source"] = snippet.unwrap_or(serde_json::json!({ "available": false }));
+        }
+    }
+}
+
+/// Lines `start..=end` (1-based) plus some context, prefixed with line numbers.
+fn snippet(contents: &str, start: usize, end: usize) -> Option<serde_json::Value> {
+    let lines: Vec<&str> = contents.lines().collect();
+    if start == 0 || end < start || end > lines.len() {
+        return None;
+    }
+    let first = start.saturating_sub(SNIPPET_CONTEXT).max(1);
+    let last = (end + SNIPPET_CONTEXT).min(lines.len());
+    let excerpt: Vec<String> = (first..=last)
+        .map(|n| format!("{}| {}", n, lines[n - 1]))
+        .collect();
+    Some(serde_json::json!({
+        "available": true,
+        "start_line": first,
+        "lines": excerpt,
+    }))
+}
+
+/// In a TYPE MISMATCH against a definition's annotation, the inferred type of the
+/// body is the last indented block before "But the type annotation on `name` ...".
+pub fn body_type(message: &str, name: &str) -> Option<String> {
+    let marker = format!("But the type annotation on `{}` says it should be:", name);
+    let (before, _) = message.split_once(&marker)?;
+    let block: Vec<&str> = before
+        .trim_end()
+        .lines()
+        .rev()
+        .take_while(|line| line.starts_with("    "))
+        .collect();
+    if block.is_empty() {
+        return None;
+    }
+    let lines: Vec<&str> = block.into_iter().rev().map(|line| &line[4..]).collect();
+    Some(lines.join("\n"))
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct SolverConflict {
+    /// Packages named in the explanation, in order of first mention
+    pub conflicting_packages: Vec<String>,
+    pub explanation: String,
+}
+
+static ANSI_ESCAPE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+
+/// Authors are GitHub accounts and may be mixed case, e.g. `NoRedInk`;
+/// package names are always lowercase.
+static COORDINATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b[A-Za-z0-9][A-Za-z0-9-]*/[a-z0-9][a-z0-9-]*\b").unwrap());
+
+/// elm-json explains unsatisfiable constraints in prose. Anything else,
+/// e.g. a network error, is not a conflict.
+pub fn solver_conflict(output: &str) -> Option<SolverConflict> {
+    let explanation = ANSI_ESCAPE.replace_all(output, "").trim().to_string();
+    let lower = explanation.to_lowercase();
+    if !["unsolvable", "could not resolve", "conflict"]
+        .iter()
+        .any(|marker| lower.contains(marker))
+    {
+        return None;
+    }
+    let mut conflicting_packages: Vec<String> = vec![];
+    for coordinate in COORDINATE.find_iter(&explanation) {
+        let coordinate = coordinate.as_str().to_string();
+        if !conflicting_packages.contains(&coordinate) {
+            conflicting_packages.push(coordinate);
+        }
+    }
+    Some(SolverConflict {
+        conflicting_packages,
+        explanation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_body_type_from_mismatch() {
+        let message = "Something is off with the body of the `probe` definition:\n\n8|     List.map\n       ^^^^^^^^\nThis `map` value is a:\n\n    (a -> b) -> List a -> List b\n\nBut the type annotation on `probe` says it should be:\n\n    ElmMcpProbe\n";
+        assert_eq!(
+            body_type(message, "probe"),
+            Some("(a -> b) -> List a -> List b".to_string())
+        );
+    }
+
+    #[test]
+    fn flattens_styled_messages() {
+        let message = serde_json::json!(["The ", { "bold": true, "string": "probe" }, " value"]);
+        assert_eq!(message_text(&message), "The probe value");
+    }
+
+    #[test]
+    fn snippet_includes_context_and_checks_bounds() {
+        let contents = "a\nb\nc\nd\ne\nf";
+        let out = snippet(contents, 3, 3).unwrap();
+        assert_eq!(out["start_line"], 1);
+        assert_eq!(
+            out["lines"],
+            serde_json::json!(["1| a", "2| b", "3| c", "4| d", "5| e"])
+        );
+        assert!(snippet(contents, 7, 7).is_none());
+    }
+
+    #[test]
+    fn parses_solver_conflicts() {
+        let stderr = "\x1b[31m-- UNSOLVABLE --\x1b[0m\n\nBecause elm/http 2.0.0 depends on elm/json 1.0.0 <= v < 2.0.0 and your project depends on elm/json 2.0.0, elm/http 2.0.0 is forbidden.";
+        let conflict = solver_conflict(stderr).unwrap();
+        assert_eq!(conflict.conflicting_packages, vec!["elm/http", "elm/json"]);
+        assert!(conflict.explanation.starts_with("-- UNSOLVABLE --"));
+        assert!(solver_conflict("error sending request").is_none());
+    }
+
+    #[test]
+    fn parses_mixed_case_authors_in_solver_conflicts() {
+        let stderr = "-- UNSOLVABLE --\n\nBecause NoRedInk/elm-json-decode-pipeline 1.0.1 depends on elm/json 1.0.0 <= v < 2.0.0 and your project depends on elm/json 2.0.0, NoRedInk/elm-json-decode-pipeline 1.0.1 is forbidden.";
+        let conflict = solver_conflict(stderr).unwrap();
+        assert_eq!(
+            conflict.conflicting_packages,
+            vec!["NoRedInk/elm-json-decode-pipeline", "elm/json"]
+        );
+    }
+}
@@ -0,0 +1,395 @@
+use rmcp::schemars;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Module {
+    pub name: String,
+    pub comment: String,
+    pub unions: Vec<Union>,
+    pub aliases: Vec<Alias>,
+    pub values: Vec<Value>,
+    pub binops: Vec<Binop>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Union {
+    pub name: String,
+    pub comment: String,
+    pub args: Vec<String>,
+    pub cases: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Alias {
+    pub name: String,
+    pub comment: String,
+    pub args: Vec<String>,
+    #[serde(rename = "type")]
+    pub tipe: String,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Value {
+    pub name: String,
+    pub comment: String,
+    #[serde(rename = "type")]
+    pub tipe: String,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Binop {
+    pub name: String,
+    pub comment: String,
+    #[serde(rename = "type")]
+    pub tipe: String,
+    pub associativity: String,
+    pub precedence: u32,
+}
+
+pub fn parse(docs: serde_json::Value) -> anyhow::Result<Vec<Module>> {
+    serde_json::from_value(docs).map_err(|_| anyhow::anyhow!("DOCS_DECODE_FAIL"))
+}
+
+pub type DocsKey = (String, String, String);
+
+/// Parsed docs keyed by `(username, package, version)`, evicting the least
+/// recently used entry once `capacity` is exceeded.
+pub struct DocsCache {
+    capacity: usize,
+    entries: std::collections::VecDeque<(DocsKey, std::sync::Arc<Vec<Module>>)>,
+}
+
+impl DocsCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Default::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &DocsKey) -> Option<std::sync::Arc<Vec<Module>>> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(index)?;
+        let docs = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(docs)
+    }
+
+    pub fn insert(&mut self, key: DocsKey, docs: std::sync::Arc<Vec<Module>>) {
+        self.entries.retain(|(k, _)| *k != key);
+        self.entries.push_back((key, docs));
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+/// Drops modules, largest first, until the serialized docs fit in `max_bytes`.
+/// Returns the kept modules in their original order and the names of those dropped.
+pub fn truncate(
+    modules: Vec<serde_json::Value>,
+    max_bytes: usize,
+) -> (Vec<serde_json::Value>, Vec<String>) {
+    let sizes: Vec<usize> = modules
+        .iter()
+        .map(|m| serde_json::to_vec(m).map(|v| v.len()).unwrap_or(0))
+        .collect();
+    // brackets plus a comma between each module
+    let mut total = 2 + sizes.iter().sum::<usize>() + sizes.len().saturating_sub(1);
+
+    let mut by_size: Vec<usize> = (0..modules.len()).collect();
+    by_size.sort_by_key(|&i| std::cmp::Reverse(sizes[i]));
+
+    let mut dropped = vec![false; modules.len()];
+    for i in by_size {
+        if total <= max_bytes {
+            break;
+        }
+        dropped[i] = true;
+        total -= sizes[i] + 1;
+    }
+
+    let mut kept = vec![];
+    let mut omitted = vec![];
+    for (module, dropped) in modules.into_iter().zip(dropped) {
+        if dropped {
+            let name = module["name"].as_str().unwrap_or_default().to_string();
+            omitted.push(name);
+        } else {
+            kept.push(module);
+        }
+    }
+    (kept, omitted)
+}
+
+pub fn signatures(module: &Module) -> Vec<String> {
+    entries(module)
+        .into_iter()
+        .map(|(_, signature)| signature)
+        .collect()
+}
+
+/// Every exposed type, alias, value and operator as `(name, signature)`.
+pub fn entries(module: &Module) -> Vec<(String, String)> {
+    let mut out = vec![];
+    for union in &module.unions {
+        let head = declaration("type", &union.name, &union.args);
+        let cases: Vec<String> = union
+            .cases
+            .iter()
+            .map(|(name, args)| {
+                std::iter::once(name.clone())
+                    .chain(args.iter().map(|arg| parenthesize(arg)))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        if cases.is_empty() {
+            out.push((union.name.clone(), head));
+        } else {
+            out.push((
+                union.name.clone(),
+                format!("{} = {}", head, cases.join(" | ")),
+            ));
+        }
+    }
+    for alias in &module.aliases {
+        out.push((
+            alias.name.clone(),
+            format!(
+                "{} = {}",
+                declaration("type alias", &alias.name, &alias.args),
+                alias.tipe
+            ),
+        ));
+    }
+    for value in &module.values {
+        out.push((
+            value.name.clone(),
+            format!("{} : {}", value.name, value.tipe),
+        ));
+    }
+    for binop in &module.binops {
+        out.push((
+            binop.name.clone(),
+            format!("({}) : {}", binop.name, binop.tipe),
+        ));
+    }
+    out
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct Example {
+    pub module: String,
+    /// `None` for examples in the module's own comment
+    pub name: Option<String>,
+    pub example: String,
+}
+
+/// Code blocks from the doc comments of a module and its exposed declarations.
+pub fn examples(module: &Module) -> Vec<Example> {
+    let comments = std::iter::once((None, &module.comment))
+        .chain(module.unions.iter().map(|u| (Some(&u.name), &u.comment)))
+        .chain(module.aliases.iter().map(|a| (Some(&a.name), &a.comment)))
+        .chain(module.values.iter().map(|v| (Some(&v.name), &v.comment)))
+        .chain(module.binops.iter().map(|b| (Some(&b.name), &b.comment)));
+    comments
+        .flat_map(|(name, comment)| {
+            code_blocks(comment)
+                .into_iter()
+                .map(move |example| Example {
+                    module: module.name.clone(),
+                    name: name.cloned(),
+                    example,
+                })
+        })
+        .collect()
+}
+
+/// Extracts fenced (```) and four-space indented markdown code blocks.
+pub fn code_blocks(comment: &str) -> Vec<String> {
+    let mut blocks = vec![];
+    let mut current: Vec<&str> = vec![];
+    let mut fenced = false;
+    let mut flush = |current: &mut Vec<&str>| {
+        while current.last().is_some_and(|line| line.trim().is_empty()) {
+            current.pop();
+        }
+        if !current.is_empty() {
+            blocks.push(current.join("\n"));
+        }
+        current.clear();
+    };
+    for line in comment.lines() {
+        if line.trim_start().starts_with("```") {
+            if fenced || !current.is_empty() {
+                flush(&mut current);
+            }
+            fenced = !fenced;
+        } else if fenced {
+            current.push(line);
+        } else if let Some(code) = line.strip_prefix("    ") {
+            current.push(code);
+        } else if line.trim().is_empty() {
+            if !current.is_empty() {
+                current.push("");
+            }
+        } else {
+            flush(&mut current);
+        }
+    }
+    flush(&mut current);
+    blocks
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, schemars::JsonSchema)]
+pub struct Section {
+    /// Empty for any text before the first heading
+    pub heading: String,
+    /// 1 to 6 for `#` to `######`, 0 for text before the first heading
+    pub level: usize,
+    pub body: String,
+}
+
+/// Splits markdown on ATX headings, ignoring `#` lines inside fenced code.
+pub fn readme_sections(markdown: &str) -> Vec<Section> {
+    let mut sections = vec![];
+    let mut current = Section {
+        heading: String::new(),
+        level: 0,
+        body: String::new(),
+    };
+    let mut fenced = false;
+    for line in markdown.lines() {
+        if line.trim_start().starts_with("```") {
+            fenced = !fenced;
+        }
+        let hashes = line.chars().take_while(|&c| c == '#').count();
+        let heading = (!fenced && (1..=6).contains(&hashes))
+            .then(|| line[hashes..].strip_prefix(' '))
+            .flatten();
+        match heading {
+            Some(heading) => {
+                let next = Section {
+                    heading: heading.trim().trim_end_matches('#').trim().to_string(),
+                    level: hashes,
+                    body: String::new(),
+                };
+                sections.push(std::mem::replace(&mut current, next));
+            }
+            None => {
+                current.body.push_str(line);
+                current.body.push('\n');
+            }
+        }
+    }
+    sections.push(current);
+    for section in &mut sections {
+        section.body = section.body.trim().to_string();
+    }
+    sections.retain(|s| s.level > 0 || !s.body.is_empty());
+    sections
+}
+
+fn declaration(keyword: &str, name: &str, args: &[String]) -> String {
+    std::iter::once(format!("{} {}", keyword, name))
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parenthesize(tipe: &str) -> String {
+    if tipe.contains(' ') && !tipe.starts_with('(') && !tipe.starts_with('{') {
+        format!("({})", tipe)
+    } else {
+        tipe.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn truncate_drops_largest_modules_first() {
+        let modules = vec![
+            json!({ "name": "Small", "comment": "" }),
+            json!({ "name": "Large", "comment": "x".repeat(100) }),
+            json!({ "name": "Medium", "comment": "x".repeat(20) }),
+        ];
+        let (kept, omitted) = truncate(modules, 100);
+        assert_eq!(omitted, vec!["Large"]);
+        assert_eq!(kept[0]["name"], "Small");
+        assert_eq!(kept[1]["name"], "Medium");
+        assert!(serde_json::to_vec(&kept).unwrap().len() <= 100);
+    }
+
+    #[test]
+    fn truncate_keeps_docs_under_limit() {
+        let modules = vec![json!({ "name": "A" })];
+        let (kept, omitted) = truncate(modules, 1000);
+        assert_eq!(kept.len(), 1);
+        assert!(omitted.is_empty());
+    }
+
+    #[test]
+    fn entries_pair_names_with_signatures() {
+        let modules = parse(json!([{
+            "name": "Maybe",
+            "comment": "",
+            "unions": [{ "name": "Maybe", "comment": "", "args": ["a"], "cases": [["Just", ["a"]], ["Nothing", []]] }],
+            "aliases": [],
+            "values": [{ "name": "withDefault", "comment": "", "type": "a -> Maybe a -> a" }],
+            "binops": []
+        }]))
+        .unwrap();
+        assert_eq!(
+            entries(&modules[0]),
+            vec![
+                (
+                    "Maybe".to_string(),
+                    "type Maybe a = Just a | Nothing".to_string()
+                ),
+                (
+                    "withDefault".to_string(),
+                    "withDefault : a -> Maybe a -> a".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_blocks_reads_indented_and_fenced_blocks() {
+        let comment = "Add numbers.\n\n    add 1 2 == 3\n\n    add 0 0 == 0\n\nOr:\n\n```elm\nadd -1 1\n```\n@docs add";
+        assert_eq!(
+            code_blocks(comment),
+            vec!["add 1 2 == 3\n\nadd 0 0 == 0", "add -1 1"]
+        );
+    }
+
+    #[test]
+    fn docs_cache_evicts_least_recently_used() {
+        let key = |p: &str| ("elm".to_string(), p.to_string(), "1.0.0".to_string());
+        let mut cache = DocsCache::new(2);
+        cache.insert(key("core"), Default::default());
+        cache.insert(key("html"), Default::default());
+        assert!(cache.get(&key("core")).is_some());
+        cache.insert(key("json"), Default::default());
+        assert!(cache.get(&key("html")).is_none());
+        assert!(cache.get(&key("core")).is_some());
+        assert!(cache.get(&key("json")).is_some());
+    }
+
+    #[test]
+    fn readme_sections_split_on_headings() {
+        let readme = "Intro\n\n# Title\n\nAbout.\n\n## Usage\n\n```bash\n# not a heading\n```\n";
+        let sections = readme_sections(readme);
+        let headings: Vec<_> = sections
+            .iter()
+            .map(|s| (s.heading.as_str(), s.level))
+            .collect();
+        assert_eq!(headings, vec![("", 0), ("Title", 1), ("Usage", 2)]);
+        assert_eq!(sections[1].body, "About.");
+        assert_eq!(sections[2].body, "```bash\n# not a heading\n```");
+    }
+}
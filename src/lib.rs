@@ -1,2 +1,9 @@
 pub mod client;
+pub mod diagnostics;
+pub mod docs;
+pub mod logging;
+pub mod project;
+pub mod refactor;
+pub mod runner;
 pub mod service;
+pub mod version;
@@ -1,7 +1,15 @@
-use elm_mcp::service::ElmService;
+use elm_mcp::{
+    client::{ElmClient, DEFAULT_REGISTRY_URL},
+    logging::JsonFormat,
+    service::{
+        ElmService, StdlibCache, DEFAULT_DOCS_CACHE_SIZE, DEFAULT_MAX_CONCURRENT_COMMANDS,
+        DEFAULT_OUTPUT_LIMIT,
+    },
+};
 use rmcp::transport::streamable_http_server::{
     session::local::LocalSessionManager, StreamableHttpService,
 };
+use std::{sync::Arc, time::Duration};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(serde::Deserialize)]
@@ -9,36 +17,168 @@ struct Env {
     port: u16,
     project_folder: String,
     entry_file: Option<String>,
+    registry_url: Option<String>,
+    source_url: Option<String>,
+    releases_cache_ttl_secs: Option<u64>,
+    log_format: Option<String>,
+    allow_random_port: Option<bool>,
+    enabled_tools: Option<Vec<String>>,
+    disabled_tools: Option<Vec<String>>,
+    max_concurrent_commands: Option<usize>,
+    /// Deprecated name for `max_concurrent_commands`
+    max_concurrent_requests: Option<usize>,
+    docs_cache_size: Option<usize>,
+    registry_requests_per_second: Option<u32>,
+    max_output_bytes: Option<usize>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let env = envy::from_env::<Env>()?;
 
+    let json_logs = match env.log_format.as_deref() {
+        None | Some("pretty") => false,
+        Some("json") => true,
+        Some(other) => anyhow::bail!(
+            "LOG_FORMAT must be \"pretty\" or \"json\", got \"{}\"",
+            other
+        ),
+    };
+
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "debug".to_string().into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with((!json_logs).then(tracing_subscriber::fmt::layer))
+        .with(json_logs.then(|| tracing_subscriber::fmt::layer().event_format(JsonFormat)))
         .init();
 
+    if env.port == 0 && !env.allow_random_port.unwrap_or(false) {
+        anyhow::bail!(
+            "PORT=0 binds a random port that clients won't know about; set ALLOW_RANDOM_PORT=true to allow it"
+        );
+    }
     let bind_address = format!("127.0.0.1:{}", env.port);
 
     let entry_file = env.entry_file.unwrap_or("./src/Main.elm".to_string());
+    let (project_folder, entry_file) = resolve_project(&env.project_folder, &entry_file)?;
+
+    let registry_url = env.registry_url.unwrap_or(DEFAULT_REGISTRY_URL.to_string());
+
+    tracing::info!(
+        project = %project_folder,
+        entry = %entry_file,
+        registry = %registry_url,
+        "starting"
+    );
+
+    let enabled_tools = env.enabled_tools;
+    let disabled_tools = env.disabled_tools.unwrap_or_default();
+    let tool_names = ElmService::tool_names();
+    for name in enabled_tools.iter().flatten().chain(&disabled_tools) {
+        if !tool_names.contains(name) {
+            anyhow::bail!(
+                "Unknown tool \"{}\" in ENABLED_TOOLS/DISABLED_TOOLS. Available: {}",
+                name,
+                tool_names.join(", ")
+            );
+        }
+    }
+
+    if env.max_concurrent_requests.is_some() {
+        tracing::warn!("MAX_CONCURRENT_REQUESTS is deprecated, use MAX_CONCURRENT_COMMANDS");
+    }
+    let max_concurrent_commands = env
+        .max_concurrent_commands
+        .or(env.max_concurrent_requests)
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_COMMANDS);
+    if max_concurrent_commands == 0 {
+        anyhow::bail!("MAX_CONCURRENT_COMMANDS must be at least 1");
+    }
+    // Excess tool calls wait for a permit instead of each spawning its own compiler.
+    let commands = Arc::new(tokio::sync::Semaphore::new(max_concurrent_commands));
+    let stdlib = StdlibCache::default();
 
-    println!("Project folder: {}", env.project_folder);
-    println!("Entry file: {}", entry_file);
+    let docs_cache_size = env.docs_cache_size.unwrap_or(DEFAULT_DOCS_CACHE_SIZE);
+    let output_limit = env.max_output_bytes.unwrap_or(DEFAULT_OUTPUT_LIMIT);
+
+    let mut client = ElmClient::new(&registry_url);
+    if let Some(source_url) = &env.source_url {
+        client = client.with_source_url(source_url);
+    }
+    if let Some(ttl) = env.releases_cache_ttl_secs {
+        client = client.with_releases_ttl(Duration::from_secs(ttl));
+    }
+    match env.registry_requests_per_second {
+        Some(0) => anyhow::bail!("REGISTRY_REQUESTS_PER_SECOND must be at least 1"),
+        Some(rate) => client = client.with_requests_per_second(rate),
+        None => {}
+    }
 
     let service = StreamableHttpService::new(
-        move || Ok(ElmService::new(&env.project_folder, &entry_file)),
+        move || {
+            Ok(ElmService::new(&project_folder, &entry_file)
+                .with_registry(Arc::new(client.clone()))
+                .with_tools(enabled_tools.as_deref(), &disabled_tools)
+                .with_docs_cache_size(docs_cache_size)
+                .with_output_limit(output_limit)
+                .with_command_limit(commands.clone())
+                .with_stdlib_cache(stdlib.clone()))
+        },
         LocalSessionManager::default().into(),
         Default::default(),
     );
 
     let router = axum::Router::new().nest_service("/mcp", service);
-    let tcp_listener = tokio::net::TcpListener::bind(bind_address).await?;
+    let tcp_listener = tokio::net::TcpListener::bind(&bind_address)
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AddrInUse => anyhow::anyhow!(
+                "failed to bind {}: address already in use — is another instance running?",
+                bind_address
+            ),
+            _ => anyhow::anyhow!("failed to bind {}: {}", bind_address, e),
+        })?;
+    tracing::info!(addr = %tcp_listener.local_addr()?, "listening");
     axum::serve(tcp_listener, router).await?;
 
     Ok(())
 }
+
+fn resolve_project(project_folder: &str, entry_file: &str) -> anyhow::Result<(String, String)> {
+    let folder = std::fs::canonicalize(project_folder).map_err(|e| {
+        anyhow::anyhow!(
+            "PROJECT_FOLDER \"{}\" could not be resolved: {}",
+            project_folder,
+            e
+        )
+    })?;
+    if !folder.is_dir() {
+        anyhow::bail!("PROJECT_FOLDER \"{}\" is not a directory", folder.display());
+    }
+
+    let entry = std::fs::canonicalize(folder.join(entry_file)).map_err(|e| {
+        anyhow::anyhow!(
+            "ENTRY_FILE \"{}\" could not be resolved in {}: {}",
+            entry_file,
+            folder.display(),
+            e
+        )
+    })?;
+    if !entry.is_file() {
+        anyhow::bail!("ENTRY_FILE \"{}\" is not a file", entry.display());
+    }
+    if !entry.starts_with(&folder) {
+        anyhow::bail!(
+            "ENTRY_FILE \"{}\" is outside of PROJECT_FOLDER \"{}\"",
+            entry.display(),
+            folder.display()
+        );
+    }
+
+    Ok((
+        folder.to_string_lossy().to_string(),
+        entry.to_string_lossy().to_string(),
+    ))
+}
@@ -0,0 +1,602 @@
+use rmcp::schemars;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ElmJson {
+    Application(Application),
+    Package(PackageProject),
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Application {
+    pub source_directories: Vec<String>,
+    pub elm_version: String,
+    pub dependencies: Dependencies,
+    pub test_dependencies: Dependencies,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct Dependencies {
+    pub direct: BTreeMap<String, String>,
+    pub indirect: BTreeMap<String, String>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackageProject {
+    pub name: String,
+    pub summary: String,
+    pub license: String,
+    pub version: String,
+    pub exposed_modules: serde_json::Value,
+    pub elm_version: String,
+    pub dependencies: BTreeMap<String, String>,
+    pub test_dependencies: BTreeMap<String, String>,
+}
+
+impl ElmJson {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ElmJson::Application(_) => "application",
+            ElmJson::Package(_) => "package",
+        }
+    }
+
+    pub fn elm_version(&self) -> &str {
+        match self {
+            ElmJson::Application(app) => &app.elm_version,
+            ElmJson::Package(pkg) => &pkg.elm_version,
+        }
+    }
+
+    /// Exact versions for applications, version constraints for packages.
+    pub fn direct_dependencies(&self) -> &BTreeMap<String, String> {
+        match self {
+            ElmJson::Application(app) => &app.dependencies.direct,
+            ElmJson::Package(pkg) => &pkg.dependencies,
+        }
+    }
+
+    /// Packages have no indirect dependencies, so theirs is always empty.
+    pub fn dependencies(&self) -> Dependencies {
+        match self {
+            ElmJson::Application(app) => app.dependencies.clone(),
+            ElmJson::Package(pkg) => Dependencies {
+                direct: pkg.dependencies.clone(),
+                indirect: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Packages always keep their modules in `src`.
+    pub fn source_directories(&self) -> Vec<String> {
+        match self {
+            ElmJson::Application(app) => app.source_directories.clone(),
+            ElmJson::Package(_) => vec!["src".to_string()],
+        }
+    }
+
+    /// Each dependency group, named by its path in elm.json.
+    pub fn dependency_sections(&self) -> Vec<(&'static str, &BTreeMap<String, String>)> {
+        match self {
+            ElmJson::Application(app) => vec![
+                ("dependencies.direct", &app.dependencies.direct),
+                ("dependencies.indirect", &app.dependencies.indirect),
+                ("test-dependencies.direct", &app.test_dependencies.direct),
+                (
+                    "test-dependencies.indirect",
+                    &app.test_dependencies.indirect,
+                ),
+            ],
+            ElmJson::Package(pkg) => vec![
+                ("dependencies", &pkg.dependencies),
+                ("test-dependencies", &pkg.test_dependencies),
+            ],
+        }
+    }
+
+    /// Direct, indirect and test dependencies together.
+    pub fn all_dependencies(&self) -> BTreeMap<String, String> {
+        match self {
+            ElmJson::Application(app) => app
+                .dependencies
+                .direct
+                .iter()
+                .chain(&app.dependencies.indirect)
+                .chain(&app.test_dependencies.direct)
+                .chain(&app.test_dependencies.indirect)
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            ElmJson::Package(pkg) => pkg
+                .dependencies
+                .iter()
+                .chain(&pkg.test_dependencies)
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// The source directories from elm.json, resolved against the project folder,
+/// filtered to those that exist.
+pub fn source_directories(project_folder: &str, elm_json: &ElmJson) -> Vec<PathBuf> {
+    elm_json
+        .source_directories()
+        .iter()
+        .filter_map(|dir| Path::new(project_folder).join(dir).canonicalize().ok())
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// The `tests` folder elm-test reads from, resolved against the project folder,
+/// if it exists.
+pub fn test_directories(project_folder: &str) -> Vec<PathBuf> {
+    Path::new(project_folder)
+        .join("tests")
+        .canonicalize()
+        .into_iter()
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// The version to look up for a dependency: the exact version in applications,
+/// the lower bound of the constraint (`1.0.0 <= v < 2.0.0`) in packages.
+pub fn dependency_version(version_or_constraint: &str) -> &str {
+    version_or_constraint
+        .split_whitespace()
+        .next()
+        .unwrap_or(version_or_constraint)
+}
+
+/// `elm/core` -> (`elm`, `core`)
+pub fn split_coordinate(name: &str) -> Option<(&str, &str)> {
+    name.split_once('/')
+}
+
+pub fn is_module_name(name: &str) -> bool {
+    name.split('.').all(|segment| {
+        let mut chars = segment.chars();
+        chars.next().is_some_and(|c| c.is_ascii_uppercase())
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Names of the modules imported by an Elm source file.
+pub fn imported_modules(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| line.strip_prefix("import "))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// The name in a file's `module`, `port module` or `effect module` declaration.
+pub fn declared_module(source: &str) -> Option<String> {
+    source.lines().find_map(|line| {
+        let rest = line
+            .strip_prefix("port ")
+            .or_else(|| line.strip_prefix("effect "))
+            .unwrap_or(line);
+        let name = rest.strip_prefix("module ")?.split_whitespace().next()?;
+        Some(name.to_string())
+    })
+}
+
+/// `src/Foo/Bar.elm` under `src` -> `Foo.Bar`
+pub fn path_module(source_directory: &Path, file: &Path) -> Option<String> {
+    let relative = file.strip_prefix(source_directory).ok()?.with_extension("");
+    let segments: Option<Vec<&str>> = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect();
+    Some(segments?.join("."))
+}
+
+/// Modification times of elm.json and every Elm file in the source directories.
+pub fn modification_times(
+    project_folder: &str,
+    source_directories: &[PathBuf],
+) -> BTreeMap<PathBuf, std::time::SystemTime> {
+    std::iter::once(Path::new(project_folder).join("elm.json"))
+        .chain(source_directories.iter().flat_map(|dir| elm_files(dir)))
+        .filter_map(|file| {
+            let modified = std::fs::metadata(&file).and_then(|m| m.modified()).ok()?;
+            Some((file, modified))
+        })
+        .collect()
+}
+
+/// `Foo.Bar` -> `Foo/Bar.elm`
+pub fn module_path(name: &str) -> PathBuf {
+    let mut path: PathBuf = name.split('.').collect();
+    path.set_extension("elm");
+    path
+}
+
+pub fn find_module(source_directories: &[PathBuf], name: &str) -> Option<PathBuf> {
+    source_directories
+        .iter()
+        .map(|dir| dir.join(module_path(name)))
+        .find(|path| path.is_file())
+}
+
+/// Total size in bytes of the files under `path`, not following symlinks.
+pub fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| disk_usage(&entry.path()))
+        .sum()
+}
+
+/// Every `.elm` file under `dir`, skipping `elm-stuff` and hidden directories.
+/// Symlinks are skipped, so the walk stays inside `dir` and can't loop.
+pub fn elm_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return files;
+    };
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if file_type.is_dir() {
+            if name != "elm-stuff" && !name.starts_with('.') {
+                files.extend(elm_files(&path));
+            }
+        } else if file_type.is_file() && path.extension().is_some_and(|ext| ext == "elm") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+#[derive(Clone, serde::Serialize, schemars::JsonSchema)]
+pub struct Installed {
+    pub package: String,
+    pub from: Option<String>,
+    pub to: String,
+    /// Where the package is now, e.g. "dependencies.direct"
+    pub section: String,
+    /// Where it was before, if it was already a dependency
+    pub from_section: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize, schemars::JsonSchema)]
+pub struct Removed {
+    pub package: String,
+    pub version: String,
+}
+
+#[derive(Clone, serde::Serialize, schemars::JsonSchema)]
+pub struct DependencyChanges {
+    pub installed: Vec<Installed>,
+    pub removed: Vec<Removed>,
+    pub unchanged: Vec<String>,
+}
+
+/// Package -> (section, version). A package that moves between sections, like an
+/// indirect dependency becoming direct, counts as installed.
+fn placed_dependencies(elm_json: &ElmJson) -> BTreeMap<&str, (&'static str, &str)> {
+    elm_json
+        .dependency_sections()
+        .into_iter()
+        .flat_map(|(section, deps)| {
+            deps.iter()
+                .map(move |(package, version)| (package.as_str(), (section, version.as_str())))
+        })
+        .collect()
+}
+
+pub fn dependency_changes(before: &ElmJson, after: &ElmJson) -> DependencyChanges {
+    let before = placed_dependencies(before);
+    let after = placed_dependencies(after);
+    let mut changes = DependencyChanges {
+        installed: vec![],
+        removed: vec![],
+        unchanged: vec![],
+    };
+    for (package, placed) in &after {
+        match before.get(package) {
+            Some(previous) if previous == placed => changes.unchanged.push(package.to_string()),
+            previous => changes.installed.push(Installed {
+                package: package.to_string(),
+                from: previous.map(|(_, version)| version.to_string()),
+                to: placed.1.to_string(),
+                section: placed.0.to_string(),
+                from_section: previous.map(|(section, _)| section.to_string()),
+            }),
+        }
+    }
+    for (package, (_, version)) in &before {
+        if !after.contains_key(package) {
+            changes.removed.push(Removed {
+                package: package.to_string(),
+                version: version.to_string(),
+            });
+        }
+    }
+    changes
+}
+
+#[derive(Clone, serde::Serialize, schemars::JsonSchema)]
+pub struct Check {
+    pub project_type: Option<String>,
+    pub problems: Vec<String>,
+}
+
+/// Checks the structure elm.json needs for its `type`, describing each
+/// missing or malformed field.
+pub fn check(value: &serde_json::Value) -> Check {
+    let mut problems = vec![];
+    let Some(root) = value.as_object() else {
+        return Check {
+            project_type: None,
+            problems: vec!["elm.json must be a JSON object".to_string()],
+        };
+    };
+
+    let project_type = root.get("type").and_then(|t| t.as_str());
+    match project_type {
+        Some("application") => {
+            match root.get("source-directories").and_then(|d| d.as_array()) {
+                Some(dirs) if dirs.iter().all(|d| d.is_string()) => {}
+                _ => problems
+                    .push("\"source-directories\" must be a list of directory names".to_string()),
+            }
+            check_string(root, "elm-version", &mut problems);
+            for field in ["dependencies", "test-dependencies"] {
+                match root.get(field).and_then(|d| d.as_object()) {
+                    Some(deps) => {
+                        for group in ["direct", "indirect"] {
+                            check_versions(deps.get(group), &format!("{field}.{group}"), &mut problems);
+                        }
+                    }
+                    None => problems.push(format!(
+                        "\"{field}\" must be an object with \"direct\" and \"indirect\" dependencies"
+                    )),
+                }
+            }
+        }
+        Some("package") => {
+            for field in ["name", "summary", "license", "version", "elm-version"] {
+                check_string(root, field, &mut problems);
+            }
+            if let Some(name) = root.get("name").and_then(|n| n.as_str())
+                && split_coordinate(name).is_none()
+            {
+                problems.push(format!(
+                    "\"name\" must look like \"username/package\", got \"{name}\""
+                ));
+            }
+            match root.get("exposed-modules") {
+                Some(serde_json::Value::Array(modules)) if modules.iter().all(|m| m.is_string()) => {}
+                Some(serde_json::Value::Object(groups))
+                    if groups.values().all(|g| {
+                        g.as_array().is_some_and(|modules| modules.iter().all(|m| m.is_string()))
+                    }) => {}
+                _ => problems.push(
+                    "\"exposed-modules\" must be a list of module names or an object of named lists"
+                        .to_string(),
+                ),
+            }
+            check_versions(root.get("dependencies"), "dependencies", &mut problems);
+            check_versions(
+                root.get("test-dependencies"),
+                "test-dependencies",
+                &mut problems,
+            );
+        }
+        Some(other) => problems.push(format!(
+            "\"type\" must be \"application\" or \"package\", got \"{other}\""
+        )),
+        None => problems.push("\"type\" is missing".to_string()),
+    }
+
+    Check {
+        project_type: project_type.map(|t| t.to_string()),
+        problems,
+    }
+}
+
+fn check_string(
+    root: &serde_json::Map<String, serde_json::Value>,
+    field: &str,
+    problems: &mut Vec<String>,
+) {
+    if !root.get(field).is_some_and(|v| v.is_string()) {
+        problems.push(format!("\"{field}\" must be a string"));
+    }
+}
+
+fn check_versions(deps: Option<&serde_json::Value>, field: &str, problems: &mut Vec<String>) {
+    match deps.and_then(|d| d.as_object()) {
+        Some(deps) => {
+            for (name, version) in deps {
+                if !version.is_string() {
+                    problems.push(format!(
+                        "\"{field}\" entry \"{name}\" must be a version string"
+                    ));
+                }
+            }
+        }
+        None => problems.push(format!("\"{field}\" must be an object of package versions")),
+    }
+}
+
+/// Reads elm.json as raw JSON so that malformed files can still be checked.
+pub fn read_value(project_folder: &str) -> anyhow::Result<serde_json::Value> {
+    let contents = std::fs::read_to_string(Path::new(project_folder).join("elm.json"))
+        .map_err(|e| anyhow::anyhow!("ELM_JSON_READ_FAIL: {e}"))?;
+    serde_json::from_str(&contents).map_err(|e| anyhow::anyhow!("ELM_JSON_DECODE_FAIL: {e}"))
+}
+
+pub fn parse(contents: &str) -> anyhow::Result<ElmJson> {
+    serde_json::from_str(contents).map_err(|e| anyhow::anyhow!("ELM_JSON_DECODE_FAIL: {e}"))
+}
+
+pub fn from_value(value: serde_json::Value) -> anyhow::Result<ElmJson> {
+    serde_json::from_value(value).map_err(|e| anyhow::anyhow!("ELM_JSON_DECODE_FAIL: {e}"))
+}
+
+pub fn read(project_folder: &str) -> anyhow::Result<ElmJson> {
+    from_value(read_value(project_folder)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_application() {
+        let elm_json = parse(
+            r#"{
+                "type": "application",
+                "source-directories": ["src"],
+                "elm-version": "0.19.1",
+                "dependencies": {
+                    "direct": { "elm/core": "1.0.5", "elm/json": "1.1.3" },
+                    "indirect": {}
+                },
+                "test-dependencies": { "direct": {}, "indirect": {} }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(elm_json.type_name(), "application");
+        assert_eq!(elm_json.elm_version(), "0.19.1");
+        assert_eq!(elm_json.direct_dependencies().len(), 2);
+    }
+
+    #[test]
+    fn parses_package() {
+        let elm_json = parse(
+            r#"{
+                "type": "package",
+                "name": "elm/json",
+                "summary": "Encode and decode JSON values",
+                "license": "BSD-3-Clause",
+                "version": "1.1.3",
+                "exposed-modules": ["Json.Decode", "Json.Encode"],
+                "elm-version": "0.19.0 <= v < 0.20.0",
+                "dependencies": { "elm/core": "1.0.0 <= v < 2.0.0" },
+                "test-dependencies": {}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(elm_json.type_name(), "package");
+        assert_eq!(
+            elm_json.direct_dependencies()["elm/core"],
+            "1.0.0 <= v < 2.0.0"
+        );
+    }
+
+    #[test]
+    fn elm_files_skip_symlinks() {
+        let dir = std::env::temp_dir().join(format!("elm-mcp-symlinks-{}", std::process::id()));
+        let outside = dir.join("outside");
+        let src = dir.join("src");
+        std::fs::create_dir_all(src.join("Page")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(src.join("Main.elm"), "module Main exposing (..)\n").unwrap();
+        std::fs::write(outside.join("Secret.elm"), "module Secret exposing (..)\n").unwrap();
+        std::os::unix::fs::symlink(&src, src.join("Page").join("Loop")).unwrap();
+        std::os::unix::fs::symlink(&outside, src.join("Outside")).unwrap();
+        std::os::unix::fs::symlink(outside.join("Secret.elm"), src.join("Secret.elm")).unwrap();
+
+        let files = elm_files(&src);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec![src.join("Main.elm")]);
+    }
+
+    #[test]
+    fn reads_declared_and_path_module_names() {
+        assert_eq!(
+            declared_module("-- header\nport module Page.Home exposing (..)\n"),
+            Some("Page.Home".to_string())
+        );
+        assert_eq!(declared_module("import Html\n"), None);
+        assert_eq!(
+            path_module(Path::new("/app/src"), Path::new("/app/src/Page/Home.elm")),
+            Some("Page.Home".to_string())
+        );
+    }
+
+    fn app(direct: &str, indirect: &str) -> ElmJson {
+        parse(&format!(
+            r#"{{
+                "type": "application",
+                "source-directories": ["src"],
+                "elm-version": "0.19.1",
+                "dependencies": {{ "direct": {{ {direct} }}, "indirect": {{ {indirect} }} }},
+                "test-dependencies": {{ "direct": {{}}, "indirect": {{}} }}
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn diffs_dependencies() {
+        let before = app(
+            r#""elm/core": "1.0.5", "elm/http": "2.0.0", "elm/url": "1.0.0""#,
+            "",
+        );
+        let after = app(
+            r#""elm/core": "1.0.5", "elm/http": "2.0.1", "elm/json": "1.1.3""#,
+            "",
+        );
+        let changes = dependency_changes(&before, &after);
+        assert_eq!(changes.unchanged, vec!["elm/core"]);
+        assert_eq!(changes.installed.len(), 2);
+        assert_eq!(changes.installed[0].package, "elm/http");
+        assert_eq!(changes.installed[0].from.as_deref(), Some("2.0.0"));
+        assert_eq!(changes.installed[1].from, None);
+        assert_eq!(changes.removed[0].package, "elm/url");
+    }
+
+    #[test]
+    fn indirect_dependency_made_direct_is_installed() {
+        let before = app(r#""elm/core": "1.0.5""#, r#""elm/json": "1.1.3""#);
+        let after = app(r#""elm/core": "1.0.5", "elm/json": "1.1.3""#, "");
+        let changes = dependency_changes(&before, &after);
+        assert_eq!(changes.unchanged, vec!["elm/core"]);
+        assert_eq!(changes.installed.len(), 1);
+        assert_eq!(changes.installed[0].package, "elm/json");
+        assert_eq!(changes.installed[0].from.as_deref(), Some("1.1.3"));
+        assert_eq!(changes.installed[0].section, "dependencies.direct");
+        assert_eq!(
+            changes.installed[0].from_section.as_deref(),
+            Some("dependencies.indirect")
+        );
+        assert!(changes.removed.is_empty());
+    }
+
+    #[test]
+    fn check_reports_malformed_fields() {
+        let check = super::check(&serde_json::json!({
+            "type": "application",
+            "source-directories": "src",
+            "elm-version": "0.19.1",
+            "dependencies": { "direct": { "elm/core": 1 }, "indirect": {} }
+        }));
+        assert_eq!(check.project_type.as_deref(), Some("application"));
+        assert_eq!(check.problems.len(), 3);
+        assert!(check.problems[0].contains("source-directories"));
+        assert!(check.problems[1].contains("elm/core"));
+        assert!(check.problems[2].contains("test-dependencies"));
+    }
+}
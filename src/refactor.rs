@@ -0,0 +1,66 @@
+use regex::Regex;
+
+/// Rewrites the `module` declaration, `import` lines and qualified references
+/// for a module rename. Submodules of `from` (e.g. `From.Sub`) are left alone.
+pub fn rename_module(source: &str, from: &str, to: &str) -> String {
+    let escaped = regex::escape(from);
+    let declaration =
+        Regex::new(&format!(r"^((?:port |effect )?module\s+){escaped}(\s|$)")).unwrap();
+    let import = Regex::new(&format!(r"^(\s*import\s+){escaped}(\s|$)")).unwrap();
+    // `A.B.c` refers to `c` in module `A.B`, so everything but the last segment is the module
+    let qualified =
+        Regex::new(r"(^|[^A-Za-z0-9_.])((?:[A-Z][A-Za-z0-9_]*\.)+)([A-Za-z_][A-Za-z0-9_]*)")
+            .unwrap();
+
+    let mut out: Vec<String> = vec![];
+    for line in source.split('\n') {
+        let trimmed = line.trim_start();
+        let line = if trimmed.starts_with("module ")
+            || trimmed.starts_with("port module ")
+            || trimmed.starts_with("effect module ")
+        {
+            declaration.replace(line, format!("${{1}}{to}${{2}}"))
+        } else if trimmed.starts_with("import ") {
+            import.replace(line, format!("${{1}}{to}${{2}}"))
+        } else {
+            qualified.replace_all(line, |caps: &regex::Captures| {
+                if caps[2].trim_end_matches('.') == from {
+                    format!("{}{}.{}", &caps[1], to, &caps[3])
+                } else {
+                    caps[0].to_string()
+                }
+            })
+        };
+        out.push(line.into_owned());
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_declaration_imports_and_references() {
+        let source = "\
+module Page.Home exposing (view)
+
+import Page.Home.Style
+import Page.Home as Home
+import Page.Homepage
+
+main = Page.Home.view Page.Home.Model (Page.Home.Style.x)";
+        let out = rename_module(source, "Page.Home", "Page.Landing");
+        assert_eq!(
+            out,
+            "\
+module Page.Landing exposing (view)
+
+import Page.Home.Style
+import Page.Landing as Home
+import Page.Homepage
+
+main = Page.Landing.view Page.Landing.Model (Page.Home.Style.x)"
+        );
+    }
+}
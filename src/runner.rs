@@ -0,0 +1,92 @@
+use futures::future::BoxFuture;
+use std::process::Output;
+
+pub trait CommandRunner: Send + Sync {
+    fn run<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [String],
+        cwd: &'a str,
+    ) -> BoxFuture<'a, std::io::Result<Output>>;
+}
+
+/// Runs commands as child processes. Dropping the returned future kills the child,
+/// so cancelled tools don't leave the compiler running.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [String],
+        cwd: &'a str,
+    ) -> BoxFuture<'a, std::io::Result<Output>> {
+        Box::pin(async move {
+            tokio::process::Command::new(program)
+                .args(args)
+                .current_dir(cwd)
+                .kill_on_drop(true)
+                .output()
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[cfg(target_os = "linux")]
+    /// `sh` arguments that record the shell's pid in `pid` and then sleep.
+    pub(crate) fn sleeper_args() -> Vec<String> {
+        vec!["-c".to_string(), "echo $$ > pid; exec sleep 30".to_string()]
+    }
+
+    #[cfg(target_os = "linux")]
+    /// Waits for a `sleeper_args` command in `dir` to write its pid.
+    pub(crate) async fn spawned_pid(dir: &Path) -> String {
+        loop {
+            if let Ok(pid) = std::fs::read_to_string(dir.join("pid"))
+                && !pid.trim().is_empty()
+            {
+                return pid.trim().to_string();
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    /// Whether `pid` is still running after giving it a second to die. A killed
+    /// child is either reaped or left as a zombie ("Z").
+    pub(crate) async fn still_running(pid: &str) -> bool {
+        let stat = std::path::PathBuf::from(format!("/proc/{}/stat", pid));
+        for _ in 0..100 {
+            let running = std::fs::read_to_string(&stat)
+                .is_ok_and(|stat| stat.split_whitespace().nth(2) != Some("Z"));
+            if !running {
+                return false;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        true
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn dropping_run_kills_the_child() {
+        let dir = std::env::temp_dir().join(format!("elm-mcp-runner-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let args = sleeper_args();
+        let cwd = dir.to_string_lossy().to_string();
+        let run = SystemRunner.run("sh", &args, &cwd);
+        let pid = tokio::select! {
+            _ = run => panic!("sleep finished before it was cancelled"),
+            pid = spawned_pid(&dir) => pid,
+        };
+
+        let running = still_running(&pid).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(!running, "child {} is still running", pid);
+    }
+}
@@ -1,26 +1,147 @@
-use crate::client::{ElmClient, Package};
+use crate::client::{ElmClient, Package, PackageRegistry};
+use crate::diagnostics;
+use crate::docs;
+use crate::project;
+use crate::refactor;
+use crate::runner::{CommandRunner, SystemRunner};
+use crate::version::{self, Change, Version};
+use futures::StreamExt;
 use rmcp::{
-    handler::server::tool::{Parameters, ToolRouter},
+    handler::server::tool::{cached_schema_for_type, Parameters, ToolCallContext, ToolRouter},
     model::{
-        CallToolResult, Content, Implementation, InitializeRequestParam, InitializeResult,
+        CallToolRequestParam, CallToolResult, Content, Implementation, InitializeRequestParam,
+        InitializeResult, ListToolsResult, PaginatedRequestParam, ProgressNotificationParam,
         ProtocolVersion, ServerCapabilities, ServerInfo,
     },
     schemars,
     service::RequestContext,
-    tool, tool_handler, tool_router, RoleServer, ServerHandler,
+    tool, tool_router, RoleServer, ServerHandler,
 };
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, LazyLock};
 use tokio::sync::Mutex;
 
+const STDLIB_PACKAGES: &[&str] = &[
+    "elm/browser",
+    "elm/bytes",
+    "elm/core",
+    "elm/file",
+    "elm/html",
+    "elm/http",
+    "elm/json",
+    "elm/parser",
+    "elm/random",
+    "elm/regex",
+    "elm/svg",
+    "elm/time",
+    "elm/url",
+    "elm/virtual-dom",
+    "elm-explorations/linear-algebra",
+    "elm-explorations/markdown",
+    "elm-explorations/test",
+    "elm-explorations/webgl",
+];
+
+/// Written under elm-stuff so `clean` removes it along with the build cache.
+const PREVIEW_FILE: &str = "elm-mcp-preview.html";
+const PREVIEW_MAX_BYTES: usize = 100_000;
+
+pub const DEFAULT_OUTPUT_LIMIT: usize = 16 * 1024;
+
+pub const DEFAULT_DOCS_CACHE_SIZE: usize = 32;
+
+pub const DEFAULT_MAX_CONCURRENT_COMMANDS: usize = 16;
+
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// Sources must be unchanged for this long before a watch revalidates, so a
+/// burst of saves compiles once.
+const WATCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+
+const REGISTRY_CONCURRENCY: usize = 8;
+
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+tokio::task_local! {
+    /// The cancellation token of the tool call being handled, so `run` can kill
+    /// its command when the client cancels, whichever tool started it.
+    static REQUEST_CANCELLATION: tokio_util::sync::CancellationToken;
+}
+
+static PROBE_COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+static PROJECT_LOCKS: LazyLock<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+/// A background poll of the project's sources, stopped when the session that
+/// started it is dropped.
+struct Watch {
+    last: Arc<Mutex<Option<LastValidation>>>,
+    _stop: tokio_util::sync::DropGuard,
+}
+
+struct LastValidation {
+    validated_at: String,
+    changed_files: Vec<String>,
+    result: CallToolResult,
+}
+
 #[derive(Clone)]
 pub struct ElmService {
     packages: Arc<Mutex<Option<Vec<Package>>>>,
-    client: ElmClient,
+    stdlib: StdlibCache,
+    docs_cache: Arc<std::sync::Mutex<docs::DocsCache>>,
+    registry: Arc<dyn PackageRegistry>,
+    runner: Arc<dyn CommandRunner>,
+    /// Shared by every session on the same project folder, held while the compiler
+    /// runs or elm.json is being changed, so those operations serialize.
+    project_lock: Arc<Mutex<()>>,
+    /// Permits for running external commands, shared across sessions so that
+    /// parallel requests queue instead of each spawning a compiler.
+    commands: Arc<tokio::sync::Semaphore>,
+    watch: Arc<Mutex<Option<Watch>>>,
+    output_limit: usize,
     project_folder: String,
     entry_file: String,
     tool_router: ToolRouter<ElmService>,
 }
 
+/// Parsed docs for `STDLIB_PACKAGES`. Clones share the same cache, so sessions
+/// given one through `ElmService::with_stdlib_cache` fetch the docs only once.
+#[derive(Clone, Default)]
+pub struct StdlibCache(Arc<Mutex<Option<Vec<StdlibPackage>>>>);
+
+#[derive(Clone)]
+struct StdlibPackage {
+    name: String,
+    version: String,
+    modules: Arc<Vec<docs::Module>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct StdlibMatch {
+    pub package: String,
+    pub version: String,
+    pub module: String,
+    pub name: String,
+    pub signature: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct Release {
+    pub version: String,
+    /// ISO-8601, UTC
+    pub published_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub package: Package,
+    pub docs_url: String,
+    /// Position in the registry's package list, which is roughly curated
+    pub rank: usize,
+}
+
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct PackageRequest {
     pub package: String,
@@ -34,9 +155,322 @@ pub struct DocsRequest {
     pub version: String,
 }
 
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct GetDocsRequest {
+    pub package: String,
+    pub username: String,
+    pub version: String,
+    /// Drop whole modules, largest first, until the docs fit in this many bytes
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(serde::Serialize)]
+pub struct TruncatedDocs {
+    pub modules: Vec<serde_json::Value>,
+    pub truncated: bool,
+    pub omitted_modules: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct ModuleRequest {
+    pub package: String,
+    pub username: String,
+    pub version: String,
+    pub module: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct ExamplesRequest {
+    pub package: String,
+    pub username: String,
+    pub version: String,
+    /// Only return examples from this module
+    pub module: Option<String>,
+    /// Only return examples from the doc comment of this value, type or operator
+    pub name: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct TopPackagesRequest {
+    pub query: String,
+    /// Maximum number of packages to return, defaults to 10
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct SuggestPackageRequest {
+    /// A possibly misspelled package name, e.g. "elm/htpp" or "htpp"
+    pub query: String,
+    /// Maximum number of suggestions to return, defaults to 5
+    pub limit: Option<usize>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Suggestion {
+    pub name: String,
+    /// Jaro-Winkler similarity, from 0 to 1
+    pub score: f64,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct CompareVersionsRequest {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct CompareVersionsResult {
+    pub change: Change,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct ProjectInfo {
+    pub elm_version: String,
+    pub project_type: String,
+    pub direct_dependency_count: usize,
+    pub entry_file: String,
+    pub compiles: bool,
+    /// Why the compiler couldn't be run, e.g. when `elm` isn't installed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compile_error: Option<String>,
+    /// A stable code for `compile_error`, like "BINARY_NOT_FOUND"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compile_error_code: Option<String>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct SourceDirectories {
+    /// Absolute paths of the existing source directories
+    pub source_directories: Vec<String>,
+    /// Absolute paths of the existing test directories
+    pub test_directories: Vec<String>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct InstalledDocs {
+    /// Module names per direct dependency, keyed by `username/package`
+    pub modules: BTreeMap<String, Vec<String>>,
+    pub errors: BTreeMap<String, String>,
+}
+
+const UNUSED_DEPENDENCY_CAVEATS: &str = "Potentially unused: a package can be needed without being imported, e.g. when it is only used by kernel code or required by another dependency, in which case it may belong in indirect dependencies instead. elm/core is never reported since it is imported implicitly. Test dependencies and tests/ are not checked.";
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct UnusedDependencies {
+    pub unused: Vec<String>,
+    pub errors: BTreeMap<String, String>,
+    pub caveats: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct CheckExpressionRequest {
+    /// Import lines without the `import` keyword, e.g. "Json.Decode as D exposing (Decoder)"
+    pub imports: Vec<String>,
+    pub expression: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct CheckImportsRequest {
+    /// Module names, e.g. "Json.Decode"
+    pub imports: Vec<String>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Installed,
+    Local,
+    Missing,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct ImportCheck {
+    pub module: String,
+    pub status: ImportStatus,
+    /// An official package exposing a missing module
+    pub suggested_package: Option<String>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct CheckImports {
+    pub imports: Vec<ImportCheck>,
+    /// Dependencies whose modules could not be listed, so "missing" may be wrong
+    pub errors: BTreeMap<String, String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct MakeFlags {
+    /// Pass `--debug`: include the time-travelling debugger and allow `Debug.log`
+    pub debug: Option<bool>,
+    /// Pass `--optimize`: production build, cannot be combined with `debug`
+    pub optimize: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct BuildPreviewRequest {
+    #[serde(flatten)]
+    pub flags: MakeFlags,
+    /// Maximum bytes of HTML to return, defaults to 100000. The file at `path` is always complete.
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct BuildPreview {
+    pub path: String,
+    pub size_bytes: usize,
+    pub truncated: bool,
+    pub html: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct TestDecoderRequest {
+    /// The project module defining the decoder, e.g. "Api.User"
+    pub module: String,
+    /// A decoder from that module, e.g. "decoder", or an expression like "Json.Decode.list Api.User.decoder"
+    pub decoder: String,
+    /// The JSON to decode
+    pub sample_json: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct DecoderResult {
+    pub ok: bool,
+    /// The decoded value as `Debug.toString` shows it, or the decode error
+    pub message: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct DependencyGraphRequest {
+    /// How many levels of dependencies to follow from the direct ones, defaults to 10
+    pub max_depth: Option<usize>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct DependencyGraph {
+    /// Each package mapped to the packages it depends on
+    pub graph: BTreeMap<String, Vec<String>>,
+    /// Packages left unexplored because `max_depth` was reached
+    pub unexplored: Vec<String>,
+    pub errors: BTreeMap<String, String>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct PackageWeight {
+    pub exposed_module_count: usize,
+    pub transitive_dependency_count: usize,
+    /// Bytes of Elm source in the exposed modules, a rough proxy for the compiled
+    /// size. None if any module's source could not be fetched.
+    pub compiled_size_estimate: Option<u64>,
+    /// Dependencies whose elm.json could not be fetched, so the count may be low
+    pub errors: BTreeMap<String, String>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct ReadmeSections {
+    pub sections: Vec<docs::Section>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct PreviewChangeRequest {
+    /// Packages to install, e.g. "elm/http"
+    #[serde(default)]
+    pub add: Vec<String>,
+    /// Packages to uninstall
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct ElmJsonPreview {
+    pub before: project::Dependencies,
+    pub after: project::Dependencies,
+    pub changes: project::DependencyChanges,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct ModuleSource {
+    pub package: String,
+    /// As written in elm.json: exact for applications, a constraint for packages
+    pub version: String,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct ElmCompatibility {
+    /// The package's `elm-version` constraint, e.g. "0.19.0 <= v < 0.20.0"
+    pub elm_version_constraint: String,
+    /// From `elm --version`
+    pub installed_elm_version: String,
+    pub compatible: bool,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct RenameModuleRequest {
+    /// Current module name, e.g. "Page.Home"
+    pub from: String,
+    /// New module name, e.g. "Page.Landing"
+    pub to: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct RenameModuleResult {
+    pub moved_from: String,
+    pub moved_to: String,
+    pub updated_files: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct ModuleNameRequest {
+    /// Module name, e.g. "Page.Home"
+    pub module: String,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct GrepRequest {
+    pub pattern: String,
+    /// Treat the pattern as a regular expression instead of a plain substring
+    pub regex: Option<bool>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ModuleNameMismatch {
+    pub file: String,
+    /// None when the file has no module declaration, which Elm treats as `Main`
+    pub declared: Option<String>,
+    pub expected: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct GrepMatch {
+    pub file: String,
+    pub line_number: usize,
+    pub line: String,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct FormatResult {
+    /// Files elm-format changed, relative to the project
+    pub formatted: Vec<String>,
+    pub checked: usize,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct CleanResult {
+    pub removed: Option<String>,
+    pub bytes_freed: u64,
+}
+
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct SearchRequest {
     pub query: String,
+    /// Only return packages with this license, e.g. "BSD-3-Clause" (case-insensitive)
+    pub license: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct StdlibRequest {
+    /// Part of a value, type or operator name, matched case-insensitively
+    pub query: String,
 }
 
 #[tool_router]
@@ -44,179 +478,1839 @@ impl ElmService {
     pub fn new(project_folder: &str, entry_file: &str) -> Self {
         Self {
             packages: Default::default(),
-            client: ElmClient::new(),
+            stdlib: Default::default(),
+            docs_cache: Arc::new(std::sync::Mutex::new(docs::DocsCache::new(
+                DEFAULT_DOCS_CACHE_SIZE,
+            ))),
+            registry: Arc::new(ElmClient::default()),
+            runner: Arc::new(SystemRunner),
+            project_lock: PROJECT_LOCKS
+                .lock()
+                .unwrap()
+                .entry(project_folder.to_string())
+                .or_default()
+                .clone(),
+            commands: Arc::new(tokio::sync::Semaphore::new(DEFAULT_MAX_CONCURRENT_COMMANDS)),
+            watch: Default::default(),
+            output_limit: DEFAULT_OUTPUT_LIMIT,
             project_folder: project_folder.to_string(),
             entry_file: entry_file.to_string(),
             tool_router: Self::tool_router(),
         }
     }
 
-    #[tool(description = "Gets the latest available package version for <USERNAME>/<PACKAGE>")]
-    async fn get_latest_package_version(
-        &self,
-        Parameters(PackageRequest { package, username }): Parameters<PackageRequest>,
-    ) -> Result<CallToolResult, rmcp::ErrorData> {
-        let latest_version = self
-            .client
-            .get_latest_package_version(&username, &package)
-            .await
-            .map_err(convert_error)?;
-        Ok(CallToolResult::success(vec![Content::text(latest_version)]))
+    pub fn tool_names() -> Vec<String> {
+        let mut names: Vec<_> = Self::tool_router()
+            .map
+            .into_keys()
+            .map(|name| name.to_string())
+            .collect();
+        names.sort();
+        names
     }
 
-    #[tool(description = "Gets the docs for a specified Elm package")]
-    async fn get_docs(
-        &self,
-        Parameters(DocsRequest {
-            package,
-            username,
-            version,
-        }): Parameters<DocsRequest>,
-    ) -> Result<CallToolResult, rmcp::ErrorData> {
-        let docs = self
-            .client
-            .get_docs(&username, &package, &version)
-            .await
-            .map_err(convert_error)?;
-        let out = Content::json(docs)?;
-        Ok(CallToolResult::success(vec![out]))
+    /// Only registers tools in `enabled` (all when `None`) that aren't in `disabled`,
+    /// so filtered tools are neither listed to clients nor callable.
+    pub fn with_tools(mut self, enabled: Option<&[String]>, disabled: &[String]) -> Self {
+        self.tool_router.map.retain(|name, _| {
+            enabled.is_none_or(|enabled| enabled.iter().any(|e| e == name))
+                && !disabled.iter().any(|d| d == name)
+        });
+        self
     }
 
-    #[tool(
-        description = "Search Elm packages by package name. Allowed characters: digits (0-9), lowercase letters (a-z), hyphen (-)"
-    )]
-    async fn search_packages(
-        &self,
-        Parameters(SearchRequest { query }): Parameters<SearchRequest>,
-    ) -> Result<CallToolResult, rmcp::ErrorData> {
-        let string_is_valid = validate_string(&query);
+    pub fn with_registry(mut self, registry: Arc<dyn PackageRegistry>) -> Self {
+        self.registry = registry;
+        self
+    }
 
-        if !string_is_valid {
-            return Err(rmcp::ErrorData::internal_error(
-                "Allowed characters: digits (0-9), lowercase letters (a-z), hyphen (-)",
-                None,
-            ));
+    /// How many packages' parsed docs to keep in memory
+    pub fn with_docs_cache_size(mut self, size: usize) -> Self {
+        self.docs_cache = Arc::new(std::sync::Mutex::new(docs::DocsCache::new(size)));
+        self
+    }
+
+    pub fn with_stdlib_cache(mut self, cache: StdlibCache) -> Self {
+        self.stdlib = cache;
+        self
+    }
+
+    /// Caps, in bytes, the stdout and stderr returned from commands
+    pub fn with_output_limit(mut self, max_bytes: usize) -> Self {
+        self.output_limit = max_bytes;
+        self
+    }
+
+    /// Shares `permits` between services, capping how many commands they run at once
+    pub fn with_command_limit(mut self, permits: Arc<tokio::sync::Semaphore>) -> Self {
+        self.commands = permits;
+        self
+    }
+
+    pub fn with_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    async fn module_docs(
+        &self,
+        username: &str,
+        package: &str,
+        version: &str,
+    ) -> anyhow::Result<Arc<Vec<docs::Module>>> {
+        let key = (
+            username.to_string(),
+            package.to_string(),
+            version.to_string(),
+        );
+        if let Some(docs) = self.docs_cache.lock().unwrap().get(&key) {
+            return Ok(docs);
         }
+        let docs = self
+            .registry
+            .get_docs(username, package, version)
+            .await
+            .and_then(docs::parse)
+            .map(Arc::new)?;
+        self.docs_cache.lock().unwrap().insert(key, docs.clone());
+        Ok(docs)
+    }
 
+    async fn all_packages(&self) -> Result<Vec<Package>, rmcp::ErrorData> {
         let mut lock = self.packages.lock().await;
-        let data = match &*lock {
-            Some(cache) => cache.clone(),
+        match &*lock {
+            Some(cache) => Ok(cache.clone()),
             None => {
                 let data = self
-                    .client
+                    .registry
                     .fetch_all_packages()
                     .await
                     .map_err(convert_error)?;
                 *lock = Some(data.clone());
-                data
+                Ok(data)
             }
-        };
-        let val = query.to_lowercase();
-        let results: Vec<_> = data
-            .into_iter()
-            .filter(|pkg| pkg.name.contains(&val))
+        }
+    }
+
+    /// Docs for the latest versions of `STDLIB_PACKAGES`, only cached once all have loaded.
+    async fn stdlib_docs(&self) -> Result<Vec<StdlibPackage>, rmcp::ErrorData> {
+        let mut lock = self.stdlib.0.lock().await;
+        if let Some(cache) = &*lock {
+            return Ok(cache.clone());
+        }
+        let names: Vec<String> = STDLIB_PACKAGES
+            .iter()
+            .map(|name| name.to_string())
             .collect();
-        let out = Content::json(results)?;
-        Ok(CallToolResult::success(vec![out]))
+        let packages: Vec<_> = futures::stream::iter(names)
+            .map(|name| async move { self.stdlib_package(name).await })
+            .buffer_unordered(REGISTRY_CONCURRENCY)
+            .collect::<Vec<anyhow::Result<_>>>()
+            .await
+            .into_iter()
+            .collect::<anyhow::Result<_>>()
+            .map_err(convert_error)?;
+        *lock = Some(packages.clone());
+        Ok(packages)
     }
 
-    #[tool(description = "Compiles and validates the current Elm project")]
-    async fn validate(&self) -> Result<CallToolResult, rmcp::ErrorData> {
-        let output = std::process::Command::new("elm")
-            .arg("make")
-            .arg("--output=/dev/null")
-            .arg("--report=json")
-            .arg(&self.entry_file)
-            .current_dir(&self.project_folder)
-            .output()
-            .map_err(|e| {
-                rmcp::ErrorData::internal_error(format!("Failed to run Elm compiler: {}", e), None)
-            })?;
+    async fn stdlib_package(&self, name: String) -> anyhow::Result<StdlibPackage> {
+        let (username, package) = project::split_coordinate(&name)
+            .ok_or_else(|| anyhow::anyhow!("INVALID_PACKAGE: {name}"))?;
+        let version = self
+            .registry
+            .get_latest_package_version(username, package)
+            .await?;
+        let modules = self.module_docs(username, package, &version).await?;
+        Ok(StdlibPackage {
+            name,
+            version,
+            modules,
+        })
+    }
 
-        let err = String::from_utf8_lossy(&output.stderr);
-        if err.is_empty() {
-            Ok(CallToolResult::success(vec![Content::text(
-                "OK".to_string(),
-            )]))
-        } else {
-            let err_data: serde_json::Value = serde_json::from_str(&err).map_err(|_| {
-                rmcp::ErrorData::internal_error("Compile error serialize fail", None)
-            })?;
-            let out = Content::json(err_data)?;
-            Ok(CallToolResult::error(vec![out]))
+    async fn matching_packages(&self, query: &str) -> Result<Vec<SearchResult>, rmcp::ErrorData> {
+        if !validate_string(query) {
+            return Err(rmcp::ErrorData::internal_error(
+                "Allowed characters: digits (0-9), lowercase letters (a-z), hyphen (-)",
+                None,
+            ));
         }
+
+        let data = self.all_packages().await?;
+        let val = query.to_lowercase();
+        Ok(data
+            .into_iter()
+            .enumerate()
+            .filter(|(_, pkg)| pkg.name.contains(&val))
+            .map(|(rank, package)| SearchResult {
+                docs_url: self.registry.package_url(&package.name, &package.version),
+                rank,
+                package,
+            })
+            .collect())
     }
 
-    #[tool(description = "Adds a package to current Elm project")]
-    async fn add_package(
-        &self,
-        Parameters(PackageRequest { package, username }): Parameters<PackageRequest>,
-    ) -> Result<CallToolResult, rmcp::ErrorData> {
-        let package = validate_package(&username, &package)?;
-        let output = std::process::Command::new("elm-json")
-            .arg("install")
-            .arg("--yes")
-            .arg(package)
-            .current_dir(&self.project_folder)
-            .output()
-            .map_err(|e| {
-                rmcp::ErrorData::internal_error(format!("Failed to install: {}", e), None)
-            })?;
-        let err = String::from_utf8_lossy(&output.stderr);
-        if err.is_empty() {
-            Ok(CallToolResult::success(vec![Content::text(
-                "OK".to_string(),
-            )]))
-        } else {
-            let out = Content::text(err);
-            Ok(CallToolResult::success(vec![out]))
+    async fn installed_modules(&self, elm_json: &project::ElmJson) -> InstalledDocs {
+        let results: Vec<_> = futures::stream::iter(elm_json.direct_dependencies().clone())
+            .map(|(name, version)| async move {
+                let modules = self.dependency_modules(&name, &version).await;
+                (name, modules)
+            })
+            .buffer_unordered(REGISTRY_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut installed = InstalledDocs {
+            modules: BTreeMap::new(),
+            errors: BTreeMap::new(),
+        };
+        for (name, modules) in results {
+            match modules {
+                Ok(modules) => {
+                    installed.modules.insert(name, modules);
+                }
+                Err(err) => {
+                    installed.errors.insert(name, err.to_string());
+                }
+            }
         }
+        installed
     }
 
-    #[tool(description = "Removes a package from current Elm project")]
-    async fn remove_package(
+    /// Follows published elm.json files breadth-first from `roots`, preferring
+    /// `pinned` versions over constraints and visiting each package once.
+    async fn resolve_graph(
         &self,
-        Parameters(PackageRequest { package, username }): Parameters<PackageRequest>,
-    ) -> Result<CallToolResult, rmcp::ErrorData> {
-        let package = validate_package(&username, &package)?;
-        let output = std::process::Command::new("elm-json")
-            .arg("uninstall")
-            .arg("--yes")
-            .arg(package)
-            .current_dir(&self.project_folder)
-            .output()
-            .map_err(|e| {
-                rmcp::ErrorData::internal_error(format!("Failed to uninstall: {}", e), None)
-            })?;
-        let err = String::from_utf8_lossy(&output.stderr);
-        if err.is_empty() {
-            Ok(CallToolResult::success(vec![Content::text(
-                "OK".to_string(),
-            )]))
-        } else {
-            let out = Content::text(err);
-            Ok(CallToolResult::success(vec![out]))
-        }
-    }
-}
+        roots: &BTreeMap<String, String>,
+        pinned: &BTreeMap<String, String>,
+        max_depth: usize,
+    ) -> DependencyGraph {
+        let mut graph = DependencyGraph {
+            graph: BTreeMap::new(),
+            unexplored: vec![],
+            errors: BTreeMap::new(),
+        };
+        let mut visited = std::collections::HashSet::new();
+        let mut level: Vec<(String, String)> = roots
+            .iter()
+            .map(|(name, version)| (name.clone(), version.clone()))
+            .collect();
 
-#[tool_handler]
-impl ServerHandler for ElmService {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            protocol_version: ProtocolVersion::V_2025_06_18,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
-            server_info: Implementation::from_build_env(),
-            instructions: Some(
-                "This server provides a variety of tools that interact with the Elm ecosystem."
-                    .to_string(),
-            ),
+        for depth in 0.. {
+            level.retain(|(name, _)| visited.insert(name.clone()));
+            if level.is_empty() {
+                break;
+            }
+            if depth >= max_depth {
+                graph.unexplored = level.into_iter().map(|(name, _)| name).collect();
+                break;
+            }
+            let results: Vec<_> = futures::stream::iter(level)
+                .map(|(name, version)| async move {
+                    let deps = self.package_dependencies(&name, &version).await;
+                    (name, deps)
+                })
+                .buffer_unordered(REGISTRY_CONCURRENCY)
+                .collect()
+                .await;
+
+            let mut next = vec![];
+            for (name, deps) in results {
+                match deps {
+                    Ok(deps) => {
+                        for (dep, constraint) in &deps {
+                            let version = pinned.get(dep).unwrap_or(constraint);
+                            next.push((dep.clone(), version.clone()));
+                        }
+                        graph.graph.insert(name, deps.into_keys().collect());
+                    }
+                    Err(err) => {
+                        graph.errors.insert(name, err.to_string());
+                    }
+                }
+            }
+            level = next;
         }
+        graph
     }
 
-    async fn initialize(
+    /// Total bytes of the given modules' published source, None if any fetch fails
+    async fn source_size(
         &self,
-        _request: InitializeRequestParam,
+        username: &str,
+        package: &str,
+        version: &str,
+        modules: Vec<String>,
+    ) -> Option<u64> {
+        let sizes: Vec<_> = futures::stream::iter(modules)
+            .map(|module| async move {
+                self.registry
+                    .get_package_source(username, package, version, &module)
+                    .await
+                    .map(|source| source.len() as u64)
+            })
+            .buffer_unordered(REGISTRY_CONCURRENCY)
+            .collect()
+            .await;
+        sizes.into_iter().sum::<anyhow::Result<u64>>().ok()
+    }
+
+    /// A published package's dependencies and their constraints
+    async fn package_dependencies(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> anyhow::Result<BTreeMap<String, String>> {
+        let (username, package) = project::split_coordinate(name)
+            .ok_or_else(|| anyhow::anyhow!("INVALID_PACKAGE: {name}"))?;
+        let elm_json = self
+            .registry
+            .get_elm_json(username, package, project::dependency_version(version))
+            .await?;
+        Ok(project::from_value(elm_json)?.direct_dependencies().clone())
+    }
+
+    /// Module names exposed by a dependency from elm.json, e.g. ("elm/core", "1.0.5")
+    async fn dependency_modules(&self, name: &str, version: &str) -> anyhow::Result<Vec<String>> {
+        let (username, package) = project::split_coordinate(name)
+            .ok_or_else(|| anyhow::anyhow!("INVALID_PACKAGE: {name}"))?;
+        let docs = self
+            .module_docs(username, package, project::dependency_version(version))
+            .await?;
+        Ok(docs.iter().map(|m| m.name.clone()).collect())
+    }
+
+    fn rename_module_files(
+        &self,
+        source_directories: &[std::path::PathBuf],
+        from: &str,
+        to: &str,
+    ) -> anyhow::Result<RenameModuleResult> {
+        let source = project::find_module(source_directories, from)
+            .ok_or_else(|| anyhow::anyhow!("MODULE_NOT_FOUND: {from}"))?;
+        let source_directory = source_directories
+            .iter()
+            .find(|dir| source.starts_with(dir))
+            .ok_or_else(|| anyhow::anyhow!("MODULE_NOT_FOUND: {from}"))?;
+        let target = source_directory.join(project::module_path(to));
+        if target.exists() {
+            anyhow::bail!("MODULE_EXISTS: {}", self.display_path(&target));
+        }
+
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&source, &target)?;
+
+        let mut updated_files = vec![];
+        for dir in source_directories {
+            for file in project::elm_files(dir) {
+                let contents = std::fs::read_to_string(&file)?;
+                let renamed = refactor::rename_module(&contents, from, to);
+                if renamed != contents {
+                    std::fs::write(&file, renamed)?;
+                    updated_files.push(self.display_path(&file));
+                }
+            }
+        }
+
+        Ok(RenameModuleResult {
+            moved_from: self.display_path(&source),
+            moved_to: self.display_path(&target),
+            updated_files,
+        })
+    }
+
+    /// Paths inside the project are shown relative to it
+    fn display_path(&self, path: &std::path::Path) -> String {
+        path.strip_prefix(&self.project_folder)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    async fn run(
+        &self,
+        program: &str,
+        args: &[String],
+        fail_message: &str,
+    ) -> Result<std::process::Output, rmcp::ErrorData> {
+        let cancellation = REQUEST_CANCELLATION
+            .try_with(|ct| ct.clone())
+            .unwrap_or_default();
+        let command = async {
+            let _permit = self
+                .commands
+                .acquire()
+                .await
+                .map_err(|e| rmcp::ErrorData::internal_error(e.to_string(), None))?;
+            self.runner
+                .run(program, args, &self.project_folder)
+                .await
+                .map_err(|e| command_error(program, fail_message, e))
+        };
+        // Dropping `command` kills the child, see `SystemRunner`.
+        tokio::select! {
+            output = command => output,
+            _ = cancellation.cancelled() => Err(request_cancelled()),
+        }
+    }
+
+    #[tool(description = "Gets the latest available package version for <USERNAME>/<PACKAGE>")]
+    async fn get_latest_package_version(
+        &self,
+        Parameters(PackageRequest { package, username }): Parameters<PackageRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        let latest_version = self
+            .registry
+            .get_latest_package_version(&username, &package)
+            .await
+            .map_err(convert_error)?;
+        Ok(CallToolResult::success(vec![Content::text(latest_version)]))
+    }
+
+    #[tool(
+        description = "Gets every published version of <USERNAME>/<PACKAGE> with its publish time, oldest first"
+    )]
+    async fn get_release_history(
+        &self,
+        Parameters(PackageRequest { package, username }): Parameters<PackageRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        let releases = self
+            .registry
+            .get_releases(&username, &package)
+            .await
+            .map_err(convert_error)?;
+        let mut releases: Vec<_> = releases.into_iter().collect();
+        releases.sort_by(|(a, a_time), (b, b_time)| {
+            a_time
+                .cmp(b_time)
+                .then_with(|| match (a.parse::<Version>(), b.parse::<Version>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    _ => a.cmp(b),
+                })
+        });
+        let history: Vec<_> = releases
+            .into_iter()
+            .map(|(version, timestamp)| Release {
+                version,
+                published_at: chrono::DateTime::from_timestamp(timestamp.into(), 0)
+                    .unwrap_or_default()
+                    .to_rfc3339(),
+            })
+            .collect();
+        let out = Content::json(history)?;
+        Ok(CallToolResult::success(vec![out]))
+    }
+
+    #[tool(description = "Gets the docs for a specified Elm package")]
+    async fn get_docs(
+        &self,
+        Parameters(GetDocsRequest {
+            package,
+            username,
+            version,
+            max_bytes,
+        }): Parameters<GetDocsRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        let docs = self
+            .registry
+            .get_docs(&username, &package, &version)
+            .await
+            .map_err(convert_error)?;
+        let out = match (max_bytes, docs) {
+            (Some(max_bytes), serde_json::Value::Array(modules)) => {
+                let (modules, omitted_modules) = docs::truncate(modules, max_bytes);
+                if omitted_modules.is_empty() {
+                    Content::json(modules)?
+                } else {
+                    Content::json(TruncatedDocs {
+                        modules,
+                        truncated: true,
+                        omitted_modules,
+                    })?
+                }
+            }
+            (_, docs) => Content::json(docs)?,
+        };
+        Ok(CallToolResult::success(vec![out]))
+    }
+
+    #[tool(description = "Lists the names of the modules exposed by a specified Elm package")]
+    async fn list_package_modules(
+        &self,
+        Parameters(DocsRequest {
+            package,
+            username,
+            version,
+        }): Parameters<DocsRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        let docs = self
+            .module_docs(&username, &package, &version)
+            .await
+            .map_err(convert_error)?;
+        let names: Vec<_> = docs.iter().map(|m| m.name.clone()).collect();
+        let out = Content::json(names)?;
+        Ok(CallToolResult::success(vec![out]))
+    }
+
+    #[tool(
+        description = "Lists the modules of every direct dependency of the current project, at the installed version",
+        output_schema = cached_schema_for_type::<InstalledDocs>()
+    )]
+    async fn get_installed_docs(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        structured(self.installed_modules(&elm_json).await)
+    }
+
+    #[tool(
+        description = "Finds which direct dependency, and which version of it, provides a module",
+        output_schema = cached_schema_for_type::<ModuleSource>()
+    )]
+    async fn installed_module_source(
+        &self,
+        Parameters(ModuleNameRequest { module }): Parameters<ModuleNameRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        if !project::is_module_name(&module) {
+            return Err(rmcp::ErrorData::invalid_params(
+                format!("Invalid module name: {}", module),
+                None,
+            ));
+        }
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let installed = self.installed_modules(&elm_json).await;
+        let package = installed
+            .modules
+            .iter()
+            .find(|(_, modules)| modules.contains(&module))
+            .map(|(package, _)| package);
+        match package {
+            Some(package) => structured(ModuleSource {
+                package: package.clone(),
+                version: elm_json.direct_dependencies()[package].clone(),
+            }),
+            None if installed.errors.is_empty() => Err(invalid_params(anyhow::anyhow!(
+                "MODULE_NOT_FOUND: {} is not exposed by any direct dependency",
+                module
+            ))),
+            None => Err(invalid_params(anyhow::anyhow!(
+                "MODULE_NOT_FOUND: {} (could not list modules of {})",
+                module,
+                installed
+                    .errors
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+        }
+    }
+
+    #[tool(
+        description = "Finds direct dependencies whose modules are never imported by the project's Elm sources",
+        output_schema = cached_schema_for_type::<UnusedDependencies>()
+    )]
+    async fn find_unused_dependencies(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let installed = self.installed_modules(&elm_json).await;
+
+        let mut imported = std::collections::HashSet::new();
+        for dir in project::source_directories(&self.project_folder, &elm_json) {
+            for file in project::elm_files(&dir) {
+                if let Ok(contents) = std::fs::read_to_string(&file) {
+                    imported.extend(project::imported_modules(&contents));
+                }
+            }
+        }
+
+        let unused = installed
+            .modules
+            .iter()
+            .filter(|(name, _)| name.as_str() != "elm/core")
+            .filter(|(_, modules)| !modules.iter().any(|m| imported.contains(m)))
+            .map(|(name, _)| name.clone())
+            .collect();
+        structured(UnusedDependencies {
+            unused,
+            errors: installed.errors,
+            caveats: UNUSED_DEPENDENCY_CAVEATS.to_string(),
+        })
+    }
+
+    #[tool(
+        description = "Checks whether each imported module is provided by a direct dependency, the project itself, or neither",
+        output_schema = cached_schema_for_type::<CheckImports>()
+    )]
+    async fn check_imports(
+        &self,
+        Parameters(CheckImportsRequest { imports }): Parameters<CheckImportsRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        if let Some(module) = imports.iter().find(|m| !project::is_module_name(m)) {
+            return Err(rmcp::ErrorData::invalid_params(
+                format!("Invalid module name: {}", module),
+                None,
+            ));
+        }
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let installed = self.installed_modules(&elm_json).await;
+        let dirs = project::source_directories(&self.project_folder, &elm_json);
+
+        let mut checks = vec![];
+        let mut stdlib = None;
+        for module in imports {
+            let status = if installed.modules.values().flatten().any(|m| *m == module) {
+                ImportStatus::Installed
+            } else if project::find_module(&dirs, &module).is_some() {
+                ImportStatus::Local
+            } else {
+                ImportStatus::Missing
+            };
+            let suggested_package = match status {
+                ImportStatus::Missing => {
+                    if stdlib.is_none() {
+                        // suggestions are best effort, a registry failure just leaves them out
+                        stdlib = Some(self.stdlib_docs().await.unwrap_or_default());
+                    }
+                    stdlib.iter().flatten().find_map(|package| {
+                        package
+                            .modules
+                            .iter()
+                            .any(|m| m.name == module)
+                            .then(|| package.name.clone())
+                    })
+                }
+                _ => None,
+            };
+            checks.push(ImportCheck {
+                module,
+                status,
+                suggested_package,
+            });
+        }
+        structured(CheckImports {
+            imports: checks,
+            errors: installed.errors,
+        })
+    }
+
+    #[tool(
+        description = "Builds the project's dependency graph by following each package's elm.json from the direct dependencies",
+        output_schema = cached_schema_for_type::<DependencyGraph>()
+    )]
+    async fn dependency_graph(
+        &self,
+        Parameters(DependencyGraphRequest { max_depth }): Parameters<DependencyGraphRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let graph = self
+            .resolve_graph(
+                elm_json.direct_dependencies(),
+                &elm_json.all_dependencies(),
+                max_depth.unwrap_or(10),
+            )
+            .await;
+        structured(graph)
+    }
+
+    #[tool(
+        description = "Gets a package's README split into sections by heading, so a single section like \"Usage\" can be read",
+        output_schema = cached_schema_for_type::<ReadmeSections>()
+    )]
+    async fn get_readme_sections(
+        &self,
+        Parameters(DocsRequest {
+            package,
+            username,
+            version,
+        }): Parameters<DocsRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        let readme = self
+            .registry
+            .get_readme(&username, &package, &version)
+            .await
+            .map_err(convert_error)?;
+        structured(match readme {
+            Some(readme) => ReadmeSections {
+                sections: docs::readme_sections(&readme),
+                note: None,
+            },
+            None => ReadmeSections {
+                sections: vec![],
+                note: Some(format!(
+                    "{}/{} {} has no README",
+                    username, package, version
+                )),
+            },
+        })
+    }
+
+    #[tool(
+        description = "Checks whether a package version's elm-version constraint allows the installed Elm compiler",
+        output_schema = cached_schema_for_type::<ElmCompatibility>()
+    )]
+    async fn check_elm_compatibility(
+        &self,
+        Parameters(DocsRequest {
+            package,
+            username,
+            version,
+        }): Parameters<DocsRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        version.parse::<Version>().map_err(invalid_params)?;
+        let elm_json = self
+            .registry
+            .get_elm_json(&username, &package, &version)
+            .await
+            .map_err(convert_error)?;
+        let constraint = elm_json["elm-version"]
+            .as_str()
+            .ok_or_else(|| convert_error(anyhow::anyhow!("ELM_JSON_DECODE_FAIL")))?
+            .to_string();
+
+        let output = self
+            .run(
+                "elm",
+                &["--version".to_string()],
+                "Failed to run Elm compiler",
+            )
+            .await?;
+        if !output.status.success() {
+            return Ok(command_result(&output, self.output_limit));
+        }
+        let installed = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let compatible = installed
+            .parse()
+            .and_then(|installed| version::satisfies(&constraint, installed))
+            .map_err(convert_error)?;
+        structured(ElmCompatibility {
+            elm_version_constraint: constraint,
+            installed_elm_version: installed,
+            compatible,
+        })
+    }
+
+    #[tool(
+        description = "Estimates the weight of adding a package: exposed modules, transitive dependencies and source size",
+        output_schema = cached_schema_for_type::<PackageWeight>()
+    )]
+    async fn package_weight(
+        &self,
+        Parameters(DocsRequest {
+            package,
+            username,
+            version,
+        }): Parameters<DocsRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        version.parse::<Version>().map_err(invalid_params)?;
+        let docs = self
+            .module_docs(&username, &package, &version)
+            .await
+            .map_err(convert_error)?;
+        let roots = self
+            .package_dependencies(&format!("{}/{}", username, package), &version)
+            .await
+            .map_err(convert_error)?;
+        let graph = self
+            .resolve_graph(&roots, &BTreeMap::new(), usize::MAX)
+            .await;
+
+        let modules: Vec<String> = docs.iter().map(|m| m.name.clone()).collect();
+        let compiled_size_estimate = self
+            .source_size(&username, &package, &version, modules)
+            .await;
+        structured(PackageWeight {
+            exposed_module_count: docs.len(),
+            transitive_dependency_count: graph.graph.len() + graph.errors.len(),
+            compiled_size_estimate,
+            errors: graph.errors,
+        })
+    }
+
+    #[tool(
+        description = "Gets the type signatures of a module's exposed values, types and aliases, one per line"
+    )]
+    async fn get_module_signatures(
+        &self,
+        Parameters(ModuleRequest {
+            package,
+            username,
+            version,
+            module,
+        }): Parameters<ModuleRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        let docs = self
+            .module_docs(&username, &package, &version)
+            .await
+            .map_err(convert_error)?;
+        let module = docs
+            .iter()
+            .find(|m| m.name == module)
+            .ok_or_else(|| invalid_params(anyhow::anyhow!("MODULE_NOT_FOUND: {}", module)))?;
+        let out = docs::signatures(module).join("\n");
+        Ok(CallToolResult::success(vec![Content::text(out)]))
+    }
+
+    #[tool(
+        description = "Gets the code examples from a package's doc comments, optionally for one module or value"
+    )]
+    async fn get_examples(
+        &self,
+        Parameters(ExamplesRequest {
+            package,
+            username,
+            version,
+            module,
+            name,
+        }): Parameters<ExamplesRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        let docs = self
+            .module_docs(&username, &package, &version)
+            .await
+            .map_err(convert_error)?;
+        if let Some(module) = &module
+            && !docs.iter().any(|m| &m.name == module)
+        {
+            return Err(invalid_params(anyhow::anyhow!(
+                "MODULE_NOT_FOUND: {}",
+                module
+            )));
+        }
+        let examples: Vec<_> = docs
+            .iter()
+            .filter(|m| module.as_ref().is_none_or(|module| &m.name == module))
+            .flat_map(docs::examples)
+            .filter(|e| name.is_none() || e.name == name)
+            .collect();
+        let out = Content::json(examples)?;
+        Ok(CallToolResult::success(vec![out]))
+    }
+
+    #[tool(description = "Gets the Elm source code of a module from a published package")]
+    async fn get_package_source(
+        &self,
+        Parameters(ModuleRequest {
+            package,
+            username,
+            version,
+            module,
+        }): Parameters<ModuleRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        validate_package(&username, &package)?;
+        version.parse::<Version>().map_err(invalid_params)?;
+        if !project::is_module_name(&module) {
+            return Err(rmcp::ErrorData::invalid_params(
+                format!("Invalid module name: {}", module),
+                None,
+            ));
+        }
+        let source = self
+            .registry
+            .get_package_source(&username, &package, &version, &module)
+            .await
+            .map_err(convert_error)?;
+        Ok(CallToolResult::success(vec![Content::text(source)]))
+    }
+
+    #[tool(
+        description = "Search Elm packages by package name. Allowed characters: digits (0-9), lowercase letters (a-z), hyphen (-)"
+    )]
+    async fn search_packages(
+        &self,
+        Parameters(SearchRequest { query, license }): Parameters<SearchRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let (results, excluded): (Vec<_>, Vec<_>) = self
+            .matching_packages(&query)
+            .await?
+            .into_iter()
+            .partition(|res| {
+                license
+                    .as_ref()
+                    .is_none_or(|license| res.package.license.eq_ignore_ascii_case(license))
+            });
+        let mut out = vec![Content::json(results)?];
+        if let Some(license) = license {
+            out.push(Content::text(format!(
+                "{} matching packages excluded by license filter \"{}\"",
+                excluded.len(),
+                license
+            )));
+        }
+        Ok(CallToolResult::success(out))
+    }
+
+    #[tool(
+        description = "Suggests published package names closest to a possibly misspelled query, most similar first"
+    )]
+    async fn suggest_package(
+        &self,
+        Parameters(SuggestPackageRequest { query, limit }): Parameters<SuggestPackageRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let packages = self.all_packages().await?;
+        let out = Content::json(suggestions(&packages, &query, limit.unwrap_or(5)))?;
+        Ok(CallToolResult::success(vec![out]))
+    }
+
+    #[tool(
+        description = "Returns the top Elm packages matching a query, exact name matches first and then by their order in the package registry"
+    )]
+    async fn top_packages(
+        &self,
+        Parameters(TopPackagesRequest { query, limit }): Parameters<TopPackagesRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let mut results = self.matching_packages(&query).await?;
+        let val = query.to_lowercase();
+        results.sort_by_key(|res| {
+            let exact = res.package.name.split('/').nth(1) == Some(val.as_str());
+            (!exact, res.rank)
+        });
+        results.truncate(limit.unwrap_or(10));
+        let out = Content::json(results)?;
+        Ok(CallToolResult::success(vec![out]))
+    }
+
+    #[tool(
+        description = "Searches the official elm/* and elm-explorations/* packages for values and types whose name contains the query"
+    )]
+    async fn search_stdlib(
+        &self,
+        Parameters(StdlibRequest { query }): Parameters<StdlibRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let val = query.to_lowercase();
+        let mut matches = vec![];
+        for package in self.stdlib_docs().await? {
+            for module in package.modules.iter() {
+                for (name, signature) in docs::entries(module) {
+                    if name.to_lowercase().contains(&val) {
+                        matches.push(StdlibMatch {
+                            package: package.name.clone(),
+                            version: package.version.clone(),
+                            module: module.name.clone(),
+                            name,
+                            signature,
+                        });
+                    }
+                }
+            }
+        }
+        let out = Content::json(matches)?;
+        Ok(CallToolResult::success(vec![out]))
+    }
+
+    #[tool(
+        description = "Classifies an upgrade between two Elm package versions as major, minor, patch, none or downgrade",
+        output_schema = cached_schema_for_type::<CompareVersionsResult>()
+    )]
+    async fn compare_versions(
+        &self,
+        Parameters(CompareVersionsRequest { from, to }): Parameters<CompareVersionsRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let from: Version = from.parse().map_err(invalid_params)?;
+        let to: Version = to.parse().map_err(invalid_params)?;
+        structured(CompareVersionsResult {
+            change: version::compare(from, to),
+        })
+    }
+
+    #[tool(description = "Compiles and validates the current Elm project")]
+    async fn validate(
+        &self,
+        context: RequestContext<RoleServer>,
+        Parameters(MakeFlags { debug, optimize }): Parameters<MakeFlags>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let flags = make_flags(debug, optimize)?;
+        with_progress(&context, "compiling...", self.compile(&flags)).await
+    }
+
+    async fn compile(&self, flags: &[String]) -> Result<CallToolResult, rmcp::ErrorData> {
+        let _guard = self.project_lock.lock().await;
+        let started = std::time::Instant::now();
+        let output = self.elm_make(&self.entry_file, "/dev/null", flags).await;
+        let duration_ms = started.elapsed().as_millis();
+        // Keeps the error's `code` alongside the timing.
+        let output = output.map_err(|mut err| {
+            let mut data = match err.data.take() {
+                Some(serde_json::Value::Object(data)) => data,
+                _ => Default::default(),
+            };
+            data.insert("duration_ms".to_string(), serde_json::json!(duration_ms));
+            err.data = Some(data.into());
+            err
+        })?;
+        let duration = serde_json::json!({ "duration_ms": duration_ms });
+
+        let mut result = self.compile_result(&output)?;
+        result.content.push(Content::json(duration)?);
+        Ok(result)
+    }
+
+    fn source_times(&self) -> BTreeMap<std::path::PathBuf, std::time::SystemTime> {
+        let dirs = project::read(&self.project_folder)
+            .map(|elm_json| project::source_directories(&self.project_folder, &elm_json))
+            .unwrap_or_default();
+        project::modification_times(&self.project_folder, &dirs)
+    }
+
+    /// Validates now, then again whenever the sources settle after a change.
+    async fn watch_sources(
+        self,
+        last: Arc<Mutex<Option<LastValidation>>>,
+        stop: tokio_util::sync::CancellationToken,
+    ) {
+        let mut seen = self.source_times();
+        let mut changed_files = vec![];
+        loop {
+            let result = self
+                .compile(&[])
+                .await
+                .unwrap_or_else(|err| CallToolResult::error(vec![Content::text(err.message)]));
+            *last.lock().await = Some(LastValidation {
+                validated_at: chrono::Utc::now().to_rfc3339(),
+                changed_files,
+                result,
+            });
+
+            let mut current = seen.clone();
+            while current == seen {
+                tokio::select! {
+                    _ = stop.cancelled() => return,
+                    _ = tokio::time::sleep(WATCH_INTERVAL) => current = self.source_times(),
+                }
+            }
+            loop {
+                tokio::select! {
+                    _ = stop.cancelled() => return,
+                    _ = tokio::time::sleep(WATCH_DEBOUNCE) => {}
+                }
+                let next = self.source_times();
+                if next == current {
+                    break;
+                }
+                current = next;
+            }
+            changed_files = current
+                .iter()
+                .filter(|(file, modified)| seen.get(*file) != Some(modified))
+                .map(|(file, _)| file)
+                .chain(seen.keys().filter(|file| !current.contains_key(*file)))
+                .map(|file| self.display_path(file))
+                .collect();
+            seen = current;
+        }
+    }
+
+    /// The compiler's JSON report with source snippets on failure, the plain output otherwise
+    fn compile_result(
+        &self,
+        output: &std::process::Output,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        if output.status.success() {
+            return Ok(command_result(output, self.output_limit));
+        }
+        let err = String::from_utf8_lossy(&output.stderr);
+        match serde_json::from_str::<serde_json::Value>(&err) {
+            Ok(mut err_data) => {
+                diagnostics::add_source_snippets(&mut err_data, &self.project_folder);
+                Ok(CallToolResult::error(vec![Content::json(err_data)?]))
+            }
+            Err(_) => Ok(command_result(output, self.output_limit)),
+        }
+    }
+
+    /// Runs an elm.json-mutating elm-json command and reports what changed in
+    /// elm.json, falling back to the raw command output if it can't be read.
+    async fn run_elm_json(
+        &self,
+        args: &[String],
+        fail_message: &str,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let _guard = self.project_lock.lock().await;
+        let check = self.check_elm_json();
+        if !check.problems.is_empty() {
+            return Ok(CallToolResult::error(vec![Content::json(check)?]));
+        }
+        let before = project::read(&self.project_folder);
+        let output = self.run("elm-json", args, fail_message).await?;
+        if !output.status.success() {
+            let text = format!(
+                "{}\n{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+            return match diagnostics::solver_conflict(&text) {
+                Some(mut conflict) => {
+                    conflict.explanation =
+                        truncate_middle(&conflict.explanation, self.output_limit).to_string();
+                    Ok(CallToolResult::error(vec![Content::json(conflict)?]))
+                }
+                None => Ok(command_result(&output, self.output_limit)),
+            };
+        }
+        match (before, project::read(&self.project_folder)) {
+            (Ok(before), Ok(after)) => structured(project::dependency_changes(&before, &after)),
+            _ => Ok(command_result(&output, self.output_limit)),
+        }
+    }
+
+    /// An unreadable or invalid elm.json is reported as a problem too.
+    fn check_elm_json(&self) -> project::Check {
+        match project::read_value(&self.project_folder) {
+            Ok(value) => project::check(&value),
+            Err(err) => project::Check {
+                project_type: None,
+                problems: vec![err.to_string()],
+            },
+        }
+    }
+
+    /// Callers must hold `project_lock`
+    async fn elm_make(
+        &self,
+        file: &str,
+        output: &str,
+        flags: &[String],
+    ) -> Result<std::process::Output, rmcp::ErrorData> {
+        let mut args = vec![
+            "make".to_string(),
+            format!("--output={}", output),
+            "--report=json".to_string(),
+        ];
+        args.extend_from_slice(flags);
+        args.push(file.to_string());
+        self.run("elm", &args, "Failed to run Elm compiler").await
+    }
+
+    #[tool(
+        description = "Compiles the entry file to a self-contained HTML page that can be opened in a browser, returning its path and contents",
+        output_schema = cached_schema_for_type::<BuildPreview>()
+    )]
+    async fn build_preview(
+        &self,
+        context: RequestContext<RoleServer>,
+        Parameters(BuildPreviewRequest {
+            flags: MakeFlags { debug, optimize },
+            max_bytes,
+        }): Parameters<BuildPreviewRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let path = std::path::Path::new(&self.project_folder)
+            .join("elm-stuff")
+            .join(PREVIEW_FILE);
+        let flags = make_flags(debug, optimize)?;
+        let _guard = self.project_lock.lock().await;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                rmcp::ErrorData::internal_error(format!("Failed to create elm-stuff: {}", e), None)
+            })?;
+        }
+        let output = with_progress(
+            &context,
+            "compiling...",
+            self.elm_make(&self.entry_file, &path.to_string_lossy(), &flags),
+        )
+        .await?;
+        if !output.status.success() {
+            return self.compile_result(&output);
+        }
+
+        let mut html = std::fs::read_to_string(&path).map_err(|e| {
+            rmcp::ErrorData::internal_error(format!("Failed to read preview: {}", e), None)
+        })?;
+        let size_bytes = html.len();
+        let mut limit = max_bytes.unwrap_or(PREVIEW_MAX_BYTES).min(size_bytes);
+        while !html.is_char_boundary(limit) {
+            limit -= 1;
+        }
+        html.truncate(limit);
+        structured(BuildPreview {
+            path: path.to_string_lossy().to_string(),
+            size_bytes,
+            truncated: limit < size_bytes,
+            html,
+        })
+    }
+
+    #[tool(
+        description = "Starts watching the project's sources for this session, revalidating after each change. Read the results with poll_validation"
+    )]
+    async fn watch_project(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let mut watch = self.watch.lock().await;
+        if watch.is_some() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "Already watching",
+            )]));
+        }
+        let last = Arc::new(Mutex::new(None));
+        let stop = tokio_util::sync::CancellationToken::new();
+        // the task's copy must not keep this session's watch, or it would never stop
+        let service = ElmService {
+            watch: Default::default(),
+            ..self.clone()
+        };
+        tokio::spawn(service.watch_sources(last.clone(), stop.clone()));
+        *watch = Some(Watch {
+            last,
+            _stop: stop.drop_guard(),
+        });
+        Ok(CallToolResult::success(vec![Content::text(
+            "Watching, the first validation is running",
+        )]))
+    }
+
+    #[tool(
+        description = "Returns the latest validation from watch_project with when it ran and which files changed before it, without compiling"
+    )]
+    async fn poll_validation(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let watch = self.watch.lock().await;
+        let Some(watch) = &*watch else {
+            return Err(rmcp::ErrorData::invalid_request(
+                "Not watching, call watch_project first",
+                None,
+            ));
+        };
+        let last = watch.last.lock().await;
+        let Some(last) = &*last else {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "The first validation is still running",
+            )]));
+        };
+        let meta = serde_json::json!({
+            "validated_at": last.validated_at,
+            "changed_files": last.changed_files,
+        });
+        let mut content = vec![Content::json(meta)?];
+        content.extend(last.result.content.iter().cloned());
+        Ok(CallToolResult {
+            content,
+            ..last.result.clone()
+        })
+    }
+
+    #[tool(
+        description = "Infers the type of an Elm expression by compiling it in a throwaway module, returning the type or the compile errors"
+    )]
+    async fn check_expression(
+        &self,
+        Parameters(CheckExpressionRequest {
+            imports,
+            expression,
+        }): Parameters<CheckExpressionRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        for import in &imports {
+            let module = import.split_whitespace().next().unwrap_or_default();
+            if import.contains('\n') || !project::is_module_name(module) {
+                return Err(rmcp::ErrorData::invalid_params(
+                    format!("Invalid import: {}", import),
+                    None,
+                ));
+            }
+        }
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let source_directory = project::source_directories(&self.project_folder, &elm_json)
+            .into_iter()
+            .next()
+            .ok_or_else(|| rmcp::ErrorData::internal_error("No source directory found", None))?;
+
+        let probe = PROBE_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let probe_module = format!("ElmMcpProbe{}{}", std::process::id(), probe);
+        let probe_dir = source_directory.join(&probe_module);
+        let probe_file = probe_dir.join("Probe.elm");
+        let source = probe_source(&format!("{}.Probe", probe_module), &imports, &expression);
+
+        let _guard = self.project_lock.lock().await;
+        let written =
+            std::fs::create_dir_all(&probe_dir).and_then(|_| std::fs::write(&probe_file, source));
+        let output = match written {
+            Ok(()) => {
+                self.elm_make(&probe_file.to_string_lossy(), "/dev/null", &[])
+                    .await
+            }
+            Err(e) => Err(rmcp::ErrorData::internal_error(
+                format!("Failed to write probe module: {}", e),
+                None,
+            )),
+        };
+        let _ = std::fs::remove_dir_all(&probe_dir);
+        let output = output?;
+
+        // Only an expression that can be any type, like `Debug.todo "x"`, satisfies
+        // the probe's deliberately wrong annotation.
+        if output.status.success() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "Could not infer a concrete type: the expression can have any type (an unconstrained type variable)",
+            )]));
+        }
+        let report: serde_json::Value = serde_json::from_slice(&output.stderr)
+            .map_err(|_| rmcp::ErrorData::internal_error("Compile error serialize fail", None))?;
+        let problems = diagnostics::problems(&report);
+        let inferred = match &problems[..] {
+            [problem] => diagnostics::body_type(&problem.message, "probe"),
+            _ => None,
+        };
+        match inferred {
+            Some(tipe) => Ok(CallToolResult::success(vec![Content::text(tipe)])),
+            None => Ok(CallToolResult::error(vec![Content::json(problems)?])),
+        }
+    }
+
+    #[tool(
+        description = "Runs a decoder against sample JSON by compiling a throwaway Elm program and running it with node, returning the decoded value or the decode error"
+    )]
+    async fn test_decoder(
+        &self,
+        Parameters(TestDecoderRequest {
+            module,
+            decoder,
+            sample_json,
+        }): Parameters<TestDecoderRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        if !project::is_module_name(&module) {
+            return Err(rmcp::ErrorData::invalid_params(
+                format!("Invalid module name: {}", module),
+                None,
+            ));
+        }
+        if decoder.contains('\n') || decoder.trim().is_empty() {
+            return Err(rmcp::ErrorData::invalid_params(
+                "decoder must be a single-line expression",
+                None,
+            ));
+        }
+        let sample: serde_json::Value = serde_json::from_str(&sample_json).map_err(|e| {
+            rmcp::ErrorData::invalid_params(format!("sample_json is not valid JSON: {}", e), None)
+        })?;
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let source_directory = project::source_directories(&self.project_folder, &elm_json)
+            .into_iter()
+            .next()
+            .ok_or_else(|| rmcp::ErrorData::internal_error("No source directory found", None))?;
+
+        let probe = PROBE_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let probe_module = format!("ElmMcpProbe{}{}", std::process::id(), probe);
+        let probe_dir = source_directory.join(&probe_module);
+        let probe_file = probe_dir.join("Decoder.elm");
+        let program = format!("{}.Decoder", probe_module);
+        let decoder = if decoder.chars().all(|c| c.is_alphanumeric() || c == '_')
+            && decoder.starts_with(|c: char| c.is_ascii_lowercase())
+        {
+            format!("{}.{}", module, decoder)
+        } else {
+            decoder
+        };
+        let source = decoder_source(&program, &module, &decoder);
+
+        let _guard = self.project_lock.lock().await;
+        let result = match std::fs::create_dir_all(&probe_dir)
+            .and_then(|_| std::fs::write(&probe_file, source))
+        {
+            Ok(()) => self.run_decoder(&probe_dir, &program, &sample).await,
+            Err(e) => Err(rmcp::ErrorData::internal_error(
+                format!("Failed to write probe module: {}", e),
+                None,
+            )),
+        };
+        let _ = std::fs::remove_dir_all(&probe_dir);
+        result
+    }
+
+    /// Compiles `probe_dir/Decoder.elm` to JS and runs it on `sample` with node.
+    /// Callers must hold `project_lock`.
+    async fn run_decoder(
+        &self,
+        probe_dir: &std::path::Path,
+        program: &str,
+        sample: &serde_json::Value,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let compiled = probe_dir.join("decoder.js");
+        let output = self
+            .elm_make(
+                &probe_dir.join("Decoder.elm").to_string_lossy(),
+                &compiled.to_string_lossy(),
+                &[],
+            )
+            .await?;
+        if !output.status.success() {
+            return self.compile_result(&output);
+        }
+
+        let harness = probe_dir.join("harness.js");
+        let init: String = program
+            .split('.')
+            .map(|segment| format!("[{:?}]", segment))
+            .collect();
+        let script = format!(
+            "const {{ Elm }} = require({});\nconst app = Elm{}.init({{ flags: {} }});\napp.ports.result.subscribe((r) => console.log(JSON.stringify(r)));\n",
+            serde_json::Value::from(compiled.to_string_lossy()),
+            init,
+            sample
+        );
+        std::fs::write(&harness, script).map_err(|e| {
+            rmcp::ErrorData::internal_error(format!("Failed to write harness: {}", e), None)
+        })?;
+        let output = self
+            .run(
+                "node",
+                &[harness.to_string_lossy().to_string()],
+                "Failed to run node",
+            )
+            .await?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let decoded = stdout
+            .lines()
+            .rev()
+            .find_map(|line| serde_json::from_str::<DecoderResult>(line).ok());
+        match decoded {
+            Some(decoded) if decoded.ok => {
+                Ok(CallToolResult::success(vec![Content::json(decoded)?]))
+            }
+            Some(decoded) => Ok(CallToolResult::error(vec![Content::json(decoded)?])),
+            None => Ok(command_result(&output, self.output_limit)),
+        }
+    }
+
+    #[tool(
+        description = "Checks that the project's elm.json has the structure required for its type, returning the type and any problems",
+        output_schema = cached_schema_for_type::<project::Check>()
+    )]
+    async fn validate_elm_json(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let check = self.check_elm_json();
+        if check.problems.is_empty() {
+            structured(check)
+        } else {
+            structured_error(check)
+        }
+    }
+
+    #[tool(
+        description = "Lists the absolute paths of the project's existing source directories and test directories",
+        output_schema = cached_schema_for_type::<SourceDirectories>()
+    )]
+    async fn get_source_directories(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let paths = |dirs: Vec<std::path::PathBuf>| {
+            dirs.iter()
+                .map(|dir| dir.to_string_lossy().to_string())
+                .collect()
+        };
+        structured(SourceDirectories {
+            source_directories: paths(project::source_directories(&self.project_folder, &elm_json)),
+            test_directories: paths(project::test_directories(&self.project_folder)),
+        })
+    }
+
+    #[tool(
+        description = "Summarizes the current Elm project: Elm version, project type, direct dependency count, entry file, and whether it currently compiles",
+        output_schema = cached_schema_for_type::<ProjectInfo>()
+    )]
+    async fn project_info(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        // The static fields are still useful when the compiler can't run.
+        let (compiles, compile_error) = match self.compile(&[]).await {
+            Ok(compiled) => (compiled.is_error != Some(true), None),
+            Err(err) if error_code_of(&err) == Some("REQUEST_CANCELLED") => return Err(err),
+            Err(err) => (false, Some(err)),
+        };
+        structured(ProjectInfo {
+            elm_version: elm_json.elm_version().to_string(),
+            project_type: elm_json.type_name().to_string(),
+            direct_dependency_count: elm_json.direct_dependencies().len(),
+            entry_file: self.entry_file.clone(),
+            compiles,
+            compile_error_code: compile_error
+                .as_ref()
+                .and_then(|err| error_code_of(err))
+                .map(str::to_string),
+            compile_error: compile_error.map(|err| err.message.to_string()),
+        })
+    }
+
+    #[tool(
+        description = "Renames an Elm module: moves its file, rewrites its module declaration and updates imports and qualified references across the project, then validates"
+    )]
+    async fn rename_module(
+        &self,
+        Parameters(RenameModuleRequest { from, to }): Parameters<RenameModuleRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        for name in [&from, &to] {
+            if !project::is_module_name(name) {
+                return Err(rmcp::ErrorData::invalid_params(
+                    format!("Invalid module name: {}", name),
+                    None,
+                ));
+            }
+        }
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let source_directories = project::source_directories(&self.project_folder, &elm_json);
+
+        let rename = {
+            let _guard = self.project_lock.lock().await;
+            self.rename_module_files(&source_directories, &from, &to)
+                .map_err(convert_error)?
+        };
+
+        let compiled = self.compile(&[]).await?;
+        let mut content = vec![Content::json(rename)?];
+        content.extend(compiled.content);
+        Ok(CallToolResult {
+            content,
+            ..compiled
+        })
+    }
+
+    #[tool(description = "Finds the source file of a project module from its module name")]
+    async fn module_to_file(
+        &self,
+        Parameters(ModuleNameRequest { module }): Parameters<ModuleNameRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        if !project::is_module_name(&module) {
+            return Err(rmcp::ErrorData::invalid_params(
+                format!("Invalid module name: {}", module),
+                None,
+            ));
+        }
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let source_directories = project::source_directories(&self.project_folder, &elm_json);
+        match project::find_module(&source_directories, &module) {
+            Some(file) => Ok(CallToolResult::success(vec![Content::text(
+                self.display_path(&file),
+            )])),
+            None => Err(invalid_params(anyhow::anyhow!(
+                "MODULE_NOT_FOUND: {} (searched {})",
+                module,
+                elm_json.source_directories().join(", ")
+            ))),
+        }
+    }
+
+    #[tool(
+        description = "Finds Elm files whose module declaration doesn't match the name implied by their path under the source directories"
+    )]
+    async fn check_module_names(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        // with nested source directories a file's name comes from the innermost one
+        let mut expected = BTreeMap::new();
+        for dir in project::source_directories(&self.project_folder, &elm_json) {
+            for file in project::elm_files(&dir) {
+                if let Some(name) = project::path_module(&dir, &file) {
+                    let depth = dir.components().count();
+                    let entry = expected.entry(file).or_insert((depth, name.clone()));
+                    if depth > entry.0 {
+                        *entry = (depth, name);
+                    }
+                }
+            }
+        }
+
+        let mut mismatches = vec![];
+        for (file, (_, expected)) in expected {
+            let Ok(source) = std::fs::read_to_string(&file) else {
+                continue;
+            };
+            let declared = project::declared_module(&source);
+            if declared.as_deref().unwrap_or("Main") != expected {
+                mismatches.push(ModuleNameMismatch {
+                    file: self.display_path(&file),
+                    declared,
+                    expected,
+                });
+            }
+        }
+        let out = Content::json(mismatches)?;
+        Ok(CallToolResult::success(vec![out]))
+    }
+
+    #[tool(
+        description = "Searches the project's Elm source directories line by line for a substring or regex"
+    )]
+    async fn grep_project(
+        &self,
+        Parameters(GrepRequest { pattern, regex }): Parameters<GrepRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let pattern = if regex.unwrap_or(false) {
+            pattern
+        } else {
+            regex::escape(&pattern)
+        };
+        let pattern = regex::Regex::new(&pattern).map_err(|e| {
+            rmcp::ErrorData::invalid_params(format!("Invalid pattern: {}", e), None)
+        })?;
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+
+        let mut matches = vec![];
+        for dir in project::source_directories(&self.project_folder, &elm_json) {
+            for file in project::elm_files(&dir) {
+                let Ok(contents) = std::fs::read_to_string(&file) else {
+                    continue;
+                };
+                for (i, line) in contents.lines().enumerate() {
+                    if pattern.is_match(line) {
+                        matches.push(GrepMatch {
+                            file: self.display_path(&file),
+                            line_number: i + 1,
+                            line: line.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        let out = Content::json(matches)?;
+        Ok(CallToolResult::success(vec![out]))
+    }
+
+    #[tool(
+        description = "Deletes the project's elm-stuff build cache, to recover from stale cache compile errors",
+        output_schema = cached_schema_for_type::<CleanResult>()
+    )]
+    async fn clean(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let _guard = self.project_lock.lock().await;
+        let elm_stuff = std::path::Path::new(&self.project_folder).join("elm-stuff");
+        if !elm_stuff.exists() {
+            return structured(CleanResult {
+                removed: None,
+                bytes_freed: 0,
+            });
+        }
+
+        let root = std::fs::canonicalize(&self.project_folder).map_err(|e| {
+            rmcp::ErrorData::internal_error(
+                format!("Failed to resolve project folder: {}", e),
+                None,
+            )
+        })?;
+        let is_link =
+            std::fs::symlink_metadata(&elm_stuff).is_ok_and(|m| m.file_type().is_symlink());
+        let resolved = std::fs::canonicalize(&elm_stuff).ok();
+        if is_link || resolved.as_deref() != Some(root.join("elm-stuff").as_path()) {
+            return Err(rmcp::ErrorData::internal_error(
+                format!(
+                    "Refusing to remove {}: not inside the project folder",
+                    elm_stuff.display()
+                ),
+                None,
+            ));
+        }
+
+        let bytes_freed = project::disk_usage(&elm_stuff);
+        std::fs::remove_dir_all(&elm_stuff).map_err(|e| {
+            rmcp::ErrorData::internal_error(format!("Failed to remove elm-stuff: {}", e), None)
+        })?;
+        structured(CleanResult {
+            removed: Some(elm_stuff.to_string_lossy().to_string()),
+            bytes_freed,
+        })
+    }
+
+    #[tool(
+        description = "Formats every Elm file in the project's source directories with elm-format, returning the files that changed",
+        output_schema = cached_schema_for_type::<FormatResult>()
+    )]
+    async fn format_project(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let elm_json = project::read(&self.project_folder).map_err(convert_error)?;
+        let files: Vec<_> = project::source_directories(&self.project_folder, &elm_json)
+            .iter()
+            .flat_map(|dir| project::elm_files(dir))
+            .collect();
+        if files.is_empty() {
+            return structured(FormatResult {
+                formatted: vec![],
+                checked: 0,
+            });
+        }
+
+        let _guard = self.project_lock.lock().await;
+        let before: Vec<_> = files.iter().map(|f| std::fs::read(f).ok()).collect();
+        let mut args = vec!["--yes".to_string()];
+        args.extend(files.iter().map(|f| f.to_string_lossy().to_string()));
+        let output = self
+            .run("elm-format", &args, "Failed to run elm-format")
+            .await?;
+        if !output.status.success() {
+            return Ok(command_result(&output, self.output_limit));
+        }
+        let formatted = files
+            .iter()
+            .zip(before)
+            .filter(|(file, before)| std::fs::read(file).ok() != *before)
+            .map(|(file, _)| self.display_path(file))
+            .collect();
+        structured(FormatResult {
+            formatted,
+            checked: files.len(),
+        })
+    }
+
+    #[tool(
+        description = "Shows how elm.json's dependencies would change after installing and uninstalling packages, without modifying the project",
+        output_schema = cached_schema_for_type::<ElmJsonPreview>()
+    )]
+    async fn preview_elm_json_change(
+        &self,
+        Parameters(PreviewChangeRequest { add, remove }): Parameters<PreviewChangeRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        for name in add.iter().chain(&remove) {
+            let (username, package) = project::split_coordinate(name)
+                .ok_or_else(|| invalid_params(anyhow::anyhow!("INVALID_PACKAGE: {name}")))?;
+            validate_package(username, package)?;
+        }
+        let before = project::read(&self.project_folder).map_err(convert_error)?;
+
+        // elm-json solves against a throwaway copy so the project's elm.json is untouched
+        let scratch = std::env::temp_dir().join(format!(
+            "elm-mcp-preview-{}-{}",
+            std::process::id(),
+            PROBE_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+        ));
+        let elm_json = scratch.join("elm.json");
+        let copied = std::fs::create_dir_all(&scratch).and_then(|_| {
+            std::fs::copy(
+                std::path::Path::new(&self.project_folder).join("elm.json"),
+                &elm_json,
+            )
+        });
+        if let Err(e) = copied {
+            let _ = std::fs::remove_dir_all(&scratch);
+            return Err(rmcp::ErrorData::internal_error(
+                format!("Failed to copy elm.json: {}", e),
+                None,
+            ));
+        }
+
+        let mut failed = None;
+        for (command, packages) in [("uninstall", &remove), ("install", &add)] {
+            if packages.is_empty() {
+                continue;
+            }
+            let mut args = vec![command.to_string(), "--yes".to_string()];
+            args.extend(packages.iter().cloned());
+            args.push("--".to_string());
+            args.push(elm_json.to_string_lossy().to_string());
+            let output = self.run("elm-json", &args, "Failed to run elm-json").await;
+            if !matches!(&output, Ok(output) if output.status.success()) {
+                failed = Some(output.map(|output| command_result(&output, self.output_limit)));
+                break;
+            }
+        }
+        let after = project::read(&scratch.to_string_lossy());
+        let _ = std::fs::remove_dir_all(&scratch);
+        if let Some(result) = failed {
+            return result;
+        }
+
+        let after = after.map_err(convert_error)?;
+        structured(ElmJsonPreview {
+            changes: project::dependency_changes(&before, &after),
+            before: before.dependencies(),
+            after: after.dependencies(),
+        })
+    }
+
+    #[tool(
+        description = "Adds a package to current Elm project",
+        output_schema = cached_schema_for_type::<project::DependencyChanges>()
+    )]
+    async fn add_package(
+        &self,
+        Parameters(PackageRequest { package, username }): Parameters<PackageRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let package = validate_package(&username, &package)?;
+        let args = ["install".to_string(), "--yes".to_string(), package.clone()];
+        let mut result = self.run_elm_json(&args, "Failed to install").await?;
+        if result.is_error == Some(true)
+            && let Ok(packages) = self.all_packages().await
+            && !packages.iter().any(|p| p.name == package)
+        {
+            let names: Vec<String> = suggestions(&packages, &package, 3)
+                .into_iter()
+                .map(|s| s.name)
+                .collect();
+            if !names.is_empty() {
+                result.content.push(Content::text(format!(
+                    "{} is not a published package. Did you mean: {}?",
+                    package,
+                    names.join(", ")
+                )));
+            }
+        }
+        Ok(result)
+    }
+
+    #[tool(
+        description = "Upgrades all dependencies to their latest compatible versions with elm-json, then validates the project"
+    )]
+    async fn upgrade_all(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let args = ["upgrade".to_string(), "--yes".to_string()];
+        let upgraded = self.run_elm_json(&args, "Failed to upgrade").await?;
+        if upgraded.is_error == Some(true) {
+            return Ok(upgraded);
+        }
+        let compiled = self.compile(&[]).await?;
+        let mut content = upgraded.content;
+        content.extend(compiled.content);
+        Ok(CallToolResult {
+            content,
+            ..compiled
+        })
+    }
+
+    #[tool(
+        description = "Removes a package from current Elm project",
+        output_schema = cached_schema_for_type::<project::DependencyChanges>()
+    )]
+    async fn remove_package(
+        &self,
+        Parameters(PackageRequest { package, username }): Parameters<PackageRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let package = validate_package(&username, &package)?;
+        let args = ["uninstall".to_string(), "--yes".to_string(), package];
+        self.run_elm_json(&args, "Failed to uninstall").await
+    }
+}
+
+impl ServerHandler for ElmService {
+    /// Runs the tool with the request's cancellation token in scope for `run`.
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let cancellation = context.ct.clone();
+        let tcc = ToolCallContext::new(self, request, context);
+        REQUEST_CANCELLATION
+            .scope(cancellation, self.tool_router.call(tcc))
+            .await
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, rmcp::ErrorData> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
+
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            protocol_version: ProtocolVersion::V_2025_06_18,
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            server_info: Implementation::from_build_env(),
+            instructions: Some(
+                "This server provides a variety of tools that interact with the Elm ecosystem."
+                    .to_string(),
+            ),
+        }
+    }
+
+    async fn initialize(
+        &self,
+        _request: InitializeRequestParam,
         context: RequestContext<RoleServer>,
     ) -> Result<InitializeResult, rmcp::ErrorData> {
         if let Some(http_request_part) = context.extensions.get::<axum::http::request::Parts>() {
@@ -228,21 +2322,1174 @@ impl ServerHandler for ElmService {
     }
 }
 
+/// A successful result whose JSON is also sent as `structuredContent`, for
+/// tools that declare an `output_schema`
+fn structured<T: serde::Serialize>(value: T) -> Result<CallToolResult, rmcp::ErrorData> {
+    let value = serde_json::to_value(value).map_err(|e| {
+        rmcp::ErrorData::internal_error(format!("Failed to serialize result: {}", e), None)
+    })?;
+    Ok(CallToolResult::structured(value))
+}
+
+fn structured_error<T: serde::Serialize>(value: T) -> Result<CallToolResult, rmcp::ErrorData> {
+    let value = serde_json::to_value(value).map_err(|e| {
+        rmcp::ErrorData::internal_error(format!("Failed to serialize result: {}", e), None)
+    })?;
+    Ok(CallToolResult::structured_error(value))
+}
+
+fn command_error(program: &str, fail_message: &str, e: std::io::Error) -> rmcp::ErrorData {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => rmcp::ErrorData::internal_error(
+            missing_binary(program),
+            Some(serde_json::json!({ "code": "BINARY_NOT_FOUND", "program": program })),
+        ),
+        _ => rmcp::ErrorData::internal_error(
+            format!("{}: {}", fail_message, e),
+            Some(serde_json::json!({ "code": "COMMAND_FAIL", "program": program })),
+        ),
+    }
+}
+
+/// The `code` from an error's data, see `error_code`.
+fn error_code_of(err: &rmcp::ErrorData) -> Option<&str> {
+    err.data.as_ref()?.get("code")?.as_str()
+}
+
+fn request_cancelled() -> rmcp::ErrorData {
+    rmcp::ErrorData::internal_error(
+        "Request cancelled",
+        Some(serde_json::json!({ "code": "REQUEST_CANCELLED" })),
+    )
+}
+
+fn missing_binary(program: &str) -> String {
+    let install = match program {
+        "elm" => "install it from https://guide.elm-lang.org/install/elm.html",
+        "elm-json" => "install it with `npm install -g elm-json`",
+        "elm-format" => "install it with `npm install -g elm-format`",
+        "node" => "install Node.js from https://nodejs.org",
+        _ => "install it",
+    };
+    format!(
+        "`{}` was not found on the PATH of the elm-mcp server process: {}, then restart the server",
+        program, install
+    )
+}
+
+fn make_flags(debug: Option<bool>, optimize: Option<bool>) -> Result<Vec<String>, rmcp::ErrorData> {
+    match (debug.unwrap_or(false), optimize.unwrap_or(false)) {
+        (true, true) => Err(rmcp::ErrorData::invalid_params(
+            "debug and optimize cannot be used together: elm make rejects --debug with --optimize",
+            None,
+        )),
+        (true, false) => Ok(vec!["--debug".to_string()]),
+        (false, true) => Ok(vec!["--optimize".to_string()]),
+        (false, false) => Ok(vec![]),
+    }
+}
+
 fn validate_string(val: &str) -> bool {
     val.chars()
         .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
 }
 
+/// `username/package` coordinates end up in registry and GitHub URLs, so both parts
+/// are checked before use. Usernames are GitHub accounts and may be mixed case,
+/// like `NoRedInk`; package names are lowercase.
 fn validate_package(username: &str, package: &str) -> Result<String, rmcp::ErrorData> {
-    if !validate_string(username) && !validate_string(package) {
-        return Err(rmcp::ErrorData::internal_error(
-            "Allowed characters: digits (0-9), lowercase letters (a-z), hyphen (-)",
+    let valid = |part: &str, allowed: fn(char) -> bool| {
+        !part.is_empty() && !part.starts_with('-') && part.chars().all(allowed)
+    };
+    if !valid(username, |c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(rmcp::ErrorData::invalid_params(
+            format!(
+                "Invalid username \"{username}\". Allowed characters: letters (a-z, A-Z), digits (0-9), hyphen (-), not leading"
+            ),
+            None,
+        ));
+    }
+    if !valid(package, |c| {
+        c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'
+    }) {
+        return Err(rmcp::ErrorData::invalid_params(
+            format!(
+                "Invalid package \"{package}\". Allowed characters: digits (0-9), lowercase letters (a-z), hyphen (-), not leading"
+            ),
             None,
         ));
     }
     Ok(format!("{username}/{package}"))
 }
 
+/// Minimum similarity for a package to be suggested.
+const SUGGESTION_THRESHOLD: f64 = 0.8;
+
+/// Packages whose name is closest to `query`. A query without a `/` is compared
+/// against the package part of each name, so "htpp" finds "elm/http".
+fn suggestions(packages: &[Package], query: &str, limit: usize) -> Vec<Suggestion> {
+    let query = query.to_lowercase();
+    let mut found: Vec<Suggestion> = packages
+        .iter()
+        .map(|package| {
+            let name = package.name.to_lowercase();
+            let target = if query.contains('/') {
+                name.as_str()
+            } else {
+                name.split('/').nth(1).unwrap_or(&name)
+            };
+            Suggestion {
+                name: package.name.clone(),
+                score: strsim::jaro_winkler(&query, target),
+            }
+        })
+        .filter(|s| s.score >= SUGGESTION_THRESHOLD)
+        .collect();
+    found.sort_by(|a, b| b.score.total_cmp(&a.score));
+    found.truncate(limit);
+    found
+}
+
+/// The annotation is deliberately wrong so the compiler reports the body's real type.
+fn probe_source(module: &str, imports: &[String], expression: &str) -> String {
+    let imports: String = imports
+        .iter()
+        .map(|import| format!("import {}\n", import))
+        .collect();
+    let body: Vec<String> = expression
+        .lines()
+        .map(|line| format!("    {}", line))
+        .collect();
+    format!(
+        "module {} exposing (probe)\n\n{}\n\ntype ElmMcpProbe\n    = ElmMcpProbe\n\n\nprobe : ElmMcpProbe\nprobe =\n{}\n",
+        module,
+        imports,
+        body.join("\n")
+    )
+}
+
+/// A worker that decodes its flags with `decoder` and reports through a port.
+/// The port is sent from `update` because messages sent from `init` go out before
+/// JS can subscribe.
+fn decoder_source(program: &str, module: &str, decoder: &str) -> String {
+    format!(
+        r#"port module {program} exposing (main)
+
+import Json.Decode
+import Process
+import Task
+import {module}
+
+
+port result : {{ ok : Bool, message : String }} -> Cmd msg
+
+
+main : Program Json.Decode.Value Json.Decode.Value ()
+main =
+    Platform.worker
+        {{ init = \flags -> ( flags, Task.perform (\_ -> ()) (Process.sleep 0) )
+        , update =
+            \_ flags ->
+                ( flags
+                , case Json.Decode.decodeValue ({decoder}) flags of
+                    Ok value ->
+                        result {{ ok = True, message = Debug.toString value }}
+
+                    Err err ->
+                        result {{ ok = False, message = Json.Decode.errorToString err }}
+                )
+        , subscriptions = \_ -> Sub.none
+        }}
+"#
+    )
+}
+
+/// Sends a progress heartbeat every `PROGRESS_INTERVAL` while `task` runs, if the
+/// client asked for progress by including a progress token in the request.
+/// Drops `task` if the client cancels the request, which kills any child process
+/// it is waiting on.
+async fn with_progress<T, F: std::future::Future<Output = Result<T, rmcp::ErrorData>>>(
+    context: &RequestContext<RoleServer>,
+    message: &str,
+    task: F,
+) -> F::Output {
+    let progress_token = context.meta.get_progress_token();
+    let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+    interval.tick().await;
+    tokio::pin!(task);
+    let mut progress = 0.0;
+    loop {
+        tokio::select! {
+            out = &mut task => return out,
+            _ = context.ct.cancelled() => {
+                return Err(request_cancelled());
+            }
+            _ = interval.tick(), if progress_token.is_some() => {
+                progress += 1.0;
+                let _ = context
+                    .peer
+                    .notify_progress(ProgressNotificationParam {
+                        progress_token: progress_token.clone().unwrap(),
+                        progress,
+                        total: None,
+                        message: Some(message.to_string()),
+                    })
+                    .await;
+            }
+        }
+    }
+}
+
+/// Keeps the first and last `max_bytes / 2` bytes, since errors tend to put
+/// context at the start and the summary at the end.
+fn truncate_middle(text: &str, max_bytes: usize) -> std::borrow::Cow<'_, str> {
+    if text.len() <= max_bytes {
+        return text.into();
+    }
+    let mut head = max_bytes / 2;
+    while !text.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = text.len() - max_bytes / 2;
+    while !text.is_char_boundary(tail) {
+        tail += 1;
+    }
+    format!(
+        "{}\n...[truncated {} bytes]...\n{}",
+        &text[..head],
+        tail - head,
+        &text[tail..]
+    )
+    .into()
+}
+
+/// stdout and stderr are each capped at `max_bytes`
+fn command_result(output: &std::process::Output, max_bytes: usize) -> CallToolResult {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = truncate_middle(&stdout, max_bytes);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = truncate_middle(&stderr, max_bytes);
+    let mut content = vec![];
+    if !stdout.trim().is_empty() {
+        content.push(Content::text(format!("stdout:\n{}", stdout)));
+    }
+    if !stderr.trim().is_empty() {
+        content.push(Content::text(format!("stderr:\n{}", stderr)));
+    }
+    if output.status.success() {
+        if content.is_empty() {
+            content.push(Content::text("OK".to_string()));
+        }
+        CallToolResult::success(content)
+    } else {
+        content.insert(
+            0,
+            Content::text(format!("Command failed: {}", output.status)),
+        );
+        CallToolResult::error(content)
+    }
+}
+
 fn convert_error(err: anyhow::Error) -> rmcp::ErrorData {
-    rmcp::ErrorData::internal_error(err.to_string(), None)
+    let message = err.to_string();
+    let data = error_code(&message);
+    rmcp::ErrorData::internal_error(message, data)
+}
+
+fn invalid_params(err: anyhow::Error) -> rmcp::ErrorData {
+    let message = err.to_string();
+    let data = error_code(&message);
+    rmcp::ErrorData::invalid_params(message, data)
+}
+
+/// Errors are tagged like `DOCS_FETCH_FAIL` or `PACKAGE_NOT_FOUND: elm/foo`;
+/// the tag is passed on as a stable `{ "code": ... }` for clients to branch on.
+fn error_code(message: &str) -> Option<serde_json::Value> {
+    let code = message.split(':').next().unwrap_or_default();
+    let is_tag = !code.is_empty()
+        && code
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    is_tag.then(|| serde_json::json!({ "code": code }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{ExitStatus, Output};
+
+    #[derive(Default)]
+    struct MockRunner {
+        outputs: std::sync::Mutex<Vec<Output>>,
+        calls: std::sync::Mutex<Vec<(String, Vec<String>)>>,
+    }
+
+    impl MockRunner {
+        fn returning(outputs: Vec<Output>) -> Arc<Self> {
+            Arc::new(Self {
+                outputs: std::sync::Mutex::new(outputs),
+                ..Default::default()
+            })
+        }
+    }
+
+    impl CommandRunner for MockRunner {
+        fn run<'a>(
+            &'a self,
+            program: &'a str,
+            args: &'a [String],
+            _cwd: &'a str,
+        ) -> futures::future::BoxFuture<'a, std::io::Result<Output>> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push((program.to_string(), args.to_vec()));
+            let next = self.outputs.lock().unwrap().remove(0);
+            Box::pin(async move { Ok(next) })
+        }
+    }
+
+    /// Sleeps for each command, recording the most commands in flight at once.
+    #[derive(Default)]
+    struct SlowRunner {
+        running: std::sync::atomic::AtomicUsize,
+        peak: std::sync::atomic::AtomicUsize,
+    }
+
+    impl CommandRunner for SlowRunner {
+        fn run<'a>(
+            &'a self,
+            _program: &'a str,
+            _args: &'a [String],
+            _cwd: &'a str,
+        ) -> futures::future::BoxFuture<'a, std::io::Result<Output>> {
+            use std::sync::atomic::Ordering::SeqCst;
+            Box::pin(async move {
+                let running = self.running.fetch_add(1, SeqCst) + 1;
+                self.peak.fetch_max(running, SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                self.running.fetch_sub(1, SeqCst);
+                Ok(output(0, "", ""))
+            })
+        }
+    }
+
+    struct MissingRunner;
+
+    impl CommandRunner for MissingRunner {
+        fn run<'a>(
+            &'a self,
+            _program: &'a str,
+            _args: &'a [String],
+            _cwd: &'a str,
+        ) -> futures::future::BoxFuture<'a, std::io::Result<Output>> {
+            Box::pin(async { Err(std::io::ErrorKind::NotFound.into()) })
+        }
+    }
+
+    struct StubRegistry {
+        packages: Option<Vec<Package>>,
+        fetches: std::sync::atomic::AtomicUsize,
+        docs_fetches: std::sync::atomic::AtomicUsize,
+    }
+
+    impl StubRegistry {
+        fn with_packages(names: &[&str]) -> Arc<Self> {
+            let packages = names
+                .iter()
+                .map(|name| Package {
+                    name: name.to_string(),
+                    summary: String::new(),
+                    license: "BSD-3-Clause".to_string(),
+                    version: "1.0.0".to_string(),
+                })
+                .collect();
+            Arc::new(Self {
+                packages: Some(packages),
+                fetches: Default::default(),
+                docs_fetches: Default::default(),
+            })
+        }
+
+        fn failing() -> Arc<Self> {
+            Arc::new(Self {
+                packages: None,
+                fetches: Default::default(),
+                docs_fetches: Default::default(),
+            })
+        }
+    }
+
+    impl PackageRegistry for StubRegistry {
+        fn get_releases<'a>(
+            &'a self,
+            _username: &'a str,
+            _package: &'a str,
+        ) -> futures::future::BoxFuture<'a, anyhow::Result<crate::client::Releases>> {
+            Box::pin(async {
+                Ok(HashMap::from([
+                    ("1.1.0".to_string(), 1_600_000_000),
+                    ("1.0.0".to_string(), 1_500_000_000),
+                ]))
+            })
+        }
+
+        fn get_latest_package_version<'a>(
+            &'a self,
+            _username: &'a str,
+            _package: &'a str,
+        ) -> futures::future::BoxFuture<'a, anyhow::Result<String>> {
+            Box::pin(async { Ok("1.0.0".to_string()) })
+        }
+
+        fn get_docs<'a>(
+            &'a self,
+            _username: &'a str,
+            _package: &'a str,
+            _version: &'a str,
+        ) -> futures::future::BoxFuture<'a, anyhow::Result<serde_json::Value>> {
+            self.docs_fetches
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Box::pin(async { Ok(serde_json::json!([])) })
+        }
+
+        fn fetch_all_packages(
+            &self,
+        ) -> futures::future::BoxFuture<'_, anyhow::Result<Vec<Package>>> {
+            self.fetches
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let packages = self.packages.clone();
+            Box::pin(async move { packages.ok_or(anyhow::anyhow!("PACKAGES_FETCH_FAIL")) })
+        }
+
+        fn get_elm_json<'a>(
+            &'a self,
+            _username: &'a str,
+            _package: &'a str,
+            _version: &'a str,
+        ) -> futures::future::BoxFuture<'a, anyhow::Result<serde_json::Value>> {
+            Box::pin(async { Err(anyhow::anyhow!("ELM_JSON_FETCH_FAIL")) })
+        }
+
+        fn get_package_source<'a>(
+            &'a self,
+            _username: &'a str,
+            _package: &'a str,
+            _version: &'a str,
+            _module: &'a str,
+        ) -> futures::future::BoxFuture<'a, anyhow::Result<String>> {
+            Box::pin(async { Err(anyhow::anyhow!("SOURCE_FETCH_FAIL")) })
+        }
+
+        fn get_readme<'a>(
+            &'a self,
+            _username: &'a str,
+            _package: &'a str,
+            _version: &'a str,
+        ) -> futures::future::BoxFuture<'a, anyhow::Result<Option<String>>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn package_url(&self, name: &str, version: &str) -> String {
+            format!("https://registry.test/packages/{}/{}/", name, version)
+        }
+    }
+
+    fn search(query: &str) -> Parameters<SearchRequest> {
+        Parameters(SearchRequest {
+            query: query.to_string(),
+            license: None,
+        })
+    }
+
+    fn service(runner: Arc<MockRunner>) -> ElmService {
+        ElmService::new("/project", "src/Main.elm")
+            .with_runner(runner)
+            .with_registry(StubRegistry::with_packages(&["elm/json"]))
+    }
+
+    /// A scratch project folder with an empty `src`, removed when dropped.
+    struct TempProject(std::path::PathBuf);
+
+    impl TempProject {
+        fn new(name: &str) -> Self {
+            static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+            let n = NEXT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let dir =
+                std::env::temp_dir().join(format!("elm-mcp-{}-{}-{}", name, std::process::id(), n));
+            std::fs::create_dir_all(dir.join("src")).unwrap();
+            Self(dir.canonicalize().unwrap())
+        }
+
+        fn folder(&self) -> String {
+            self.0.to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TempProject {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Writes an application elm.json with no dependencies.
+    fn write_app_elm_json(dir: &std::path::Path, source_directories: &[&str]) {
+        let elm_json = serde_json::json!({
+            "type": "application",
+            "source-directories": source_directories,
+            "elm-version": "0.19.1",
+            "dependencies": { "direct": {}, "indirect": {} },
+            "test-dependencies": { "direct": {}, "indirect": {} }
+        });
+        std::fs::write(dir.join("elm.json"), elm_json.to_string()).unwrap();
+    }
+
+    /// A service on `project`, which must have an elm.json, for tools that check it.
+    fn elm_json_service(project: &TempProject, runner: Arc<dyn CommandRunner>) -> ElmService {
+        write_app_elm_json(&project.0, &["src"]);
+        ElmService::new(&project.folder(), "src/Main.elm")
+            .with_runner(runner)
+            .with_registry(StubRegistry::with_packages(&["elm/json"]))
+    }
+
+    fn elm_json_request() -> Parameters<PackageRequest> {
+        Parameters(PackageRequest {
+            username: "elm".to_string(),
+            package: "json".to_string(),
+        })
+    }
+
+    fn output(code: i32, stdout: &str, stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    fn texts(result: &CallToolResult) -> Vec<String> {
+        result
+            .content
+            .iter()
+            .filter_map(|c| c.as_text().map(|t| t.text.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn zero_exit_with_warnings_is_success() {
+        let result = command_result(&output(0, "", "warning: something"), DEFAULT_OUTPUT_LIMIT);
+        assert_eq!(result.is_error, Some(false));
+        assert!(texts(&result)
+            .iter()
+            .any(|t| t.contains("warning: something")));
+    }
+
+    #[test]
+    fn zero_exit_without_output_is_ok() {
+        let result = command_result(&output(0, "", ""), DEFAULT_OUTPUT_LIMIT);
+        assert_eq!(result.is_error, Some(false));
+        assert_eq!(texts(&result), vec!["OK".to_string()]);
+    }
+
+    #[test]
+    fn non_zero_exit_is_error_with_stderr() {
+        let result = command_result(&output(1, "", "no solution"), DEFAULT_OUTPUT_LIMIT);
+        assert_eq!(result.is_error, Some(true));
+        assert!(texts(&result).iter().any(|t| t.contains("no solution")));
+    }
+
+    #[test]
+    fn non_zero_exit_without_stderr_is_error() {
+        let result = command_result(&output(2, "", ""), DEFAULT_OUTPUT_LIMIT);
+        assert_eq!(result.is_error, Some(true));
+    }
+
+    #[test]
+    fn truncate_middle_keeps_head_and_tail() {
+        let text = format!("{}{}{}", "a".repeat(10), "b".repeat(100), "c".repeat(10));
+        let out = truncate_middle(&text, 20);
+        assert_eq!(
+            out,
+            format!(
+                "{}\n...[truncated 100 bytes]...\n{}",
+                "a".repeat(10),
+                "c".repeat(10)
+            )
+        );
+        assert_eq!(truncate_middle("short", 20), "short");
+    }
+
+    #[tokio::test]
+    async fn validate_runs_elm_make_on_entry_file() {
+        let runner = MockRunner::returning(vec![output(0, "", "")]);
+        let result = service(runner.clone()).compile(&[]).await.unwrap();
+        assert_eq!(result.is_error, Some(false));
+        assert!(result.content[1]
+            .as_text()
+            .unwrap()
+            .text
+            .contains("duration_ms"));
+        let calls = runner.calls.lock().unwrap();
+        assert_eq!(calls[0].0, "elm");
+        assert_eq!(calls[0].1.last().unwrap(), "src/Main.elm");
+    }
+
+    /// Runs the `sleeper_args` shell command whatever program is asked for.
+    #[cfg(target_os = "linux")]
+    struct SleepRunner;
+
+    #[cfg(target_os = "linux")]
+    impl CommandRunner for SleepRunner {
+        fn run<'a>(
+            &'a self,
+            _program: &'a str,
+            _args: &'a [String],
+            cwd: &'a str,
+        ) -> futures::future::BoxFuture<'a, std::io::Result<Output>> {
+            Box::pin(async move {
+                let args = crate::runner::tests::sleeper_args();
+                SystemRunner.run("sh", &args, cwd).await
+            })
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn cancelling_a_tool_call_kills_its_command() {
+        use crate::runner::tests::{spawned_pid, still_running};
+        use rmcp::model::{CallToolRequestParam, ClientRequest, Request};
+        use rmcp::service::PeerRequestOptions;
+        use rmcp::ServiceExt;
+
+        let project = TempProject::new("cancel-tool");
+        write_app_elm_json(&project.0, &["src"]);
+        let service =
+            ElmService::new(&project.folder(), "src/Main.elm").with_runner(Arc::new(SleepRunner));
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        let server = tokio::spawn(service.clone().serve(server_io));
+        let client = ().serve(client_io).await.unwrap();
+        let _server = server.await.unwrap().unwrap();
+
+        // project_info compiles without going through with_progress.
+        let request = ClientRequest::CallToolRequest(Request::new(CallToolRequestParam {
+            name: "project_info".into(),
+            arguments: None,
+        }));
+        let handle = client
+            .peer()
+            .send_cancellable_request(request, PeerRequestOptions::no_options())
+            .await
+            .unwrap();
+        let pid = spawned_pid(&project.0).await;
+        handle.cancel(None).await.unwrap();
+
+        assert!(!still_running(&pid).await, "child {} is still running", pid);
+        // The project lock is released too.
+        let _guard = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            service.project_lock.lock(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn cancelling_a_request_kills_the_command() {
+        use crate::runner::tests::{sleeper_args, spawned_pid, still_running};
+        use rmcp::ServiceExt;
+
+        let project = TempProject::new("cancel");
+        let service = ElmService::new(&project.folder(), "src/Main.elm");
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        let server = tokio::spawn(service.clone().serve(server_io));
+        let _client = ().serve(client_io).await.unwrap();
+        let server = server.await.unwrap().unwrap();
+        let context = RequestContext {
+            ct: tokio_util::sync::CancellationToken::new(),
+            id: rmcp::model::NumberOrString::Number(1),
+            meta: Default::default(),
+            extensions: Default::default(),
+            peer: server.peer().clone(),
+        };
+
+        let args = sleeper_args();
+        let task = with_progress(
+            &context,
+            "compiling...",
+            service.run("sh", &args, "Failed to run sh"),
+        );
+        let cancel = async {
+            let pid = spawned_pid(&project.0).await;
+            context.ct.cancel();
+            pid
+        };
+        let (result, pid) = tokio::join!(task, cancel);
+
+        let running = still_running(&pid).await;
+        assert_eq!(result.unwrap_err().message, "Request cancelled");
+        assert!(!running, "child {} is still running", pid);
+    }
+
+    #[tokio::test]
+    async fn command_limit_is_shared_across_services() {
+        let runner = Arc::new(SlowRunner::default());
+        let permits = Arc::new(tokio::sync::Semaphore::new(2));
+        let services: Vec<ElmService> = (0..3)
+            .map(|i| {
+                ElmService::new(&format!("/limited-{}", i), "src/Main.elm")
+                    .with_runner(runner.clone())
+                    .with_command_limit(permits.clone())
+            })
+            .collect();
+        let results =
+            futures::future::join_all(services.iter().map(|service| service.compile(&[]))).await;
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(runner.peak.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn poll_validation_returns_watch_result() {
+        let runner = MockRunner::returning(vec![output(0, "", "")]);
+        let service = service(runner);
+        assert!(service.poll_validation().await.is_err());
+        service.watch_project().await.unwrap();
+        let result = loop {
+            let result = service.poll_validation().await.unwrap();
+            if result.content.len() > 1 {
+                break result;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        };
+        assert_eq!(result.is_error, Some(false));
+        assert!(result.content[0]
+            .as_text()
+            .unwrap()
+            .text
+            .contains("validated_at"));
+    }
+
+    #[tokio::test]
+    async fn missing_binary_names_the_tool() {
+        let service =
+            ElmService::new("/project", "src/Main.elm").with_runner(Arc::new(MissingRunner));
+        let err = service.compile(&[]).await.unwrap_err();
+        assert!(err.message.contains("`elm` was not found"));
+        let data = err.data.unwrap();
+        assert_eq!(data["code"], "BINARY_NOT_FOUND");
+        assert!(data["duration_ms"].is_u64());
+        let project = TempProject::new("missing");
+        let err = elm_json_service(&project, Arc::new(MissingRunner))
+            .remove_package(elm_json_request())
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("`elm-json` was not found"));
+    }
+
+    #[tokio::test]
+    async fn rename_module_reports_project_relative_paths() {
+        let project = TempProject::new("rename");
+        let dir = &project.0;
+        write_app_elm_json(dir, &["src"]);
+        std::fs::write(
+            dir.join("src/Old.elm"),
+            "module Old exposing (x)\n\nx = 1\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("src/Main.elm"),
+            "module Main exposing (main)\n\nimport Old\n\nmain = Old.x\n",
+        )
+        .unwrap();
+        let service = ElmService::new(&project.folder(), "src/Main.elm")
+            .with_runner(MockRunner::returning(vec![output(0, "", "")]));
+        let result = service
+            .rename_module(Parameters(RenameModuleRequest {
+                from: "Old".to_string(),
+                to: "New".to_string(),
+            }))
+            .await;
+        let rename: serde_json::Value = serde_json::from_str(&texts(&result.unwrap())[0]).unwrap();
+        assert_eq!(rename["moved_from"], "src/Old.elm");
+        assert_eq!(rename["moved_to"], "src/New.elm");
+        assert_eq!(
+            rename["updated_files"],
+            serde_json::json!(["src/Main.elm", "src/New.elm"])
+        );
+    }
+
+    #[tokio::test]
+    async fn source_directories_are_absolute_and_exist() {
+        let project = TempProject::new("dirs");
+        let root = &project.0;
+        std::fs::create_dir_all(root.join("tests")).unwrap();
+        write_app_elm_json(root, &["src", "missing"]);
+        let service = ElmService::new(&project.folder(), "src/Main.elm");
+        let result = service.get_source_directories().await;
+        let dirs = result.unwrap().structured_content.unwrap();
+        assert_eq!(
+            dirs,
+            serde_json::json!({
+                "source_directories": [root.join("src")],
+                "test_directories": [root.join("tests")],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn project_info_survives_a_missing_compiler() {
+        let project = TempProject::new("info");
+        write_app_elm_json(&project.0, &["src"]);
+        let service =
+            ElmService::new(&project.folder(), "src/Main.elm").with_runner(Arc::new(MissingRunner));
+        let info = service
+            .project_info()
+            .await
+            .unwrap()
+            .structured_content
+            .unwrap();
+        assert_eq!(info["project_type"], "application");
+        assert_eq!(info["elm_version"], "0.19.1");
+        assert_eq!(info["compiles"], false);
+        assert_eq!(info["compile_error_code"], "BINARY_NOT_FOUND");
+        assert!(info["compile_error"]
+            .as_str()
+            .unwrap()
+            .contains("`elm` was not found"));
+    }
+
+    #[tokio::test]
+    async fn check_expression_reports_unconstrained_types() {
+        let project = TempProject::new("probe");
+        write_app_elm_json(&project.0, &["src"]);
+        let service = ElmService::new(&project.folder(), "src/Main.elm")
+            .with_runner(MockRunner::returning(vec![output(0, "", "")]));
+        let result = service
+            .check_expression(Parameters(CheckExpressionRequest {
+                imports: vec![],
+                expression: "Debug.todo \"x\"".to_string(),
+            }))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        let text = &texts(&result)[0];
+        assert!(text.contains("Could not infer a concrete type"));
+        assert!(!text.contains("ElmMcpProbe"));
+    }
+
+    #[tokio::test]
+    async fn test_decoder_rejects_invalid_sample() {
+        let service = ElmService::new("/project", "src/Main.elm");
+        let err = service
+            .test_decoder(Parameters(TestDecoderRequest {
+                module: "Api.User".to_string(),
+                decoder: "decoder".to_string(),
+                sample_json: "{ not json".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+        assert!(err.message.contains("sample_json"));
+    }
+
+    #[test]
+    fn validate_package_checks_both_parts() {
+        assert_eq!(
+            validate_package("NoRedInk", "elm-json-decode-pipeline").unwrap(),
+            "NoRedInk/elm-json-decode-pipeline"
+        );
+        for (username, package) in [
+            ("elm", "../../x"),
+            ("elm/core", "json"),
+            ("elm", "-json"),
+            ("-elm", "json"),
+            ("elm", "Json"),
+            ("", "json"),
+        ] {
+            let err = validate_package(username, package).unwrap_err();
+            assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+        }
+    }
+
+    #[test]
+    fn make_flags_rejects_debug_with_optimize() {
+        assert_eq!(make_flags(Some(true), None).unwrap(), vec!["--debug"]);
+        assert!(make_flags(None, None).unwrap().is_empty());
+        let err = make_flags(Some(true), Some(true)).unwrap_err();
+        assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn validate_returns_compiler_report_on_failure() {
+        let report = r#"{"type":"compile-errors","errors":[]}"#;
+        let runner = MockRunner::returning(vec![output(1, "", report)]);
+        let result = service(runner).compile(&[]).await.unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert!(result.content[0]
+            .as_text()
+            .unwrap()
+            .text
+            .contains("compile-errors"));
+    }
+
+    #[tokio::test]
+    async fn add_package_failure_is_error() {
+        let runner = MockRunner::returning(vec![output(1, "", "no solution")]);
+        let project = TempProject::new("elm-json");
+        let result = elm_json_service(&project, runner.clone())
+            .add_package(elm_json_request())
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        let calls = runner.calls.lock().unwrap();
+        assert_eq!(calls[0].0, "elm-json");
+        assert_eq!(calls[0].1, vec!["install", "--yes", "elm/json"]);
+    }
+
+    #[tokio::test]
+    async fn add_package_suggests_similar_names() {
+        let runner = MockRunner::returning(vec![output(1, "", "no such package")]);
+        let project = TempProject::new("elm-json");
+        let service = elm_json_service(&project, runner)
+            .with_registry(StubRegistry::with_packages(&["elm/http", "elm/json"]));
+        let result = service
+            .add_package(Parameters(PackageRequest {
+                username: "elm".to_string(),
+                package: "htpp".to_string(),
+            }))
+            .await
+            .unwrap();
+        assert_eq!(
+            texts(&result).last().unwrap(),
+            "elm/htpp is not a published package. Did you mean: elm/http?"
+        );
+    }
+
+    #[test]
+    fn suggestions_rank_by_similarity() {
+        let packages: Vec<Package> = ["elm/http", "elm/html", "elm/json"]
+            .iter()
+            .map(|name| Package {
+                name: name.to_string(),
+                summary: String::new(),
+                license: String::new(),
+                version: "1.0.0".to_string(),
+            })
+            .collect();
+        let names: Vec<String> = suggestions(&packages, "htpp", 5)
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names[0], "elm/http");
+        assert!(!names.contains(&"elm/json".to_string()));
+        assert!(suggestions(&packages, "zzzz", 5).is_empty());
+    }
+
+    #[tokio::test]
+    async fn solver_conflict_explanation_is_truncated() {
+        let stderr = format!(
+            "-- UNSOLVABLE --\nBecause elm/json 1.1.3 conflicts with elm/core 2.0.0\n{}",
+            "x".repeat(1000)
+        );
+        let runner = MockRunner::returning(vec![output(1, "", &stderr)]);
+        let project = TempProject::new("elm-json");
+        let result = elm_json_service(&project, runner)
+            .with_output_limit(100)
+            .add_package(elm_json_request())
+            .await
+            .unwrap();
+        let conflict: serde_json::Value = serde_json::from_str(&texts(&result)[0]).unwrap();
+        let explanation = conflict["explanation"].as_str().unwrap();
+        assert!(explanation.len() < 200);
+        assert!(explanation.contains("truncated"));
+    }
+
+    #[tokio::test]
+    async fn add_package_reports_solver_conflicts() {
+        let stderr = "-- UNSOLVABLE --\nBecause elm/json 1.1.3 conflicts with elm/core 2.0.0";
+        let runner = MockRunner::returning(vec![output(1, "", stderr)]);
+        let project = TempProject::new("elm-json");
+        let result = elm_json_service(&project, runner)
+            .add_package(elm_json_request())
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        let conflict: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(
+            conflict["conflicting_packages"],
+            serde_json::json!(["elm/json", "elm/core"])
+        );
+    }
+
+    #[tokio::test]
+    async fn remove_package_success() {
+        let runner = MockRunner::returning(vec![output(0, "", "")]);
+        let project = TempProject::new("elm-json");
+        let result = elm_json_service(&project, runner.clone())
+            .remove_package(elm_json_request())
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(false));
+        assert!(result.structured_content.unwrap()["removed"].is_array());
+        assert_eq!(runner.calls.lock().unwrap()[0].1[0], "uninstall");
+    }
+
+    #[tokio::test]
+    async fn unreadable_elm_json_stops_elm_json() {
+        let runner = MockRunner::returning(vec![]);
+        let result = service(runner.clone())
+            .add_package(elm_json_request())
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert!(texts(&result)[0].contains("ELM_JSON_READ_FAIL"));
+        assert!(runner.calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn search_packages_filters_and_caches() {
+        let registry = StubRegistry::with_packages(&[
+            "elm/json",
+            "elm/http",
+            "NoRedInk/elm-json-decode-pipeline",
+        ]);
+        let service = ElmService::new("/project", "src/Main.elm").with_registry(registry.clone());
+        let result = service.search_packages(search("json")).await.unwrap();
+        let text = &result.content[0].as_text().unwrap().text;
+        let found: Vec<serde_json::Value> = serde_json::from_str(text).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0]["name"], "elm/json");
+        assert_eq!(
+            found[0]["docs_url"],
+            "https://registry.test/packages/elm/json/1.0.0/"
+        );
+        service.search_packages(search("http")).await.unwrap();
+        assert_eq!(
+            registry.fetches.load(std::sync::atomic::Ordering::SeqCst),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn dependency_modules_share_the_docs_cache() {
+        let registry = StubRegistry::with_packages(&[]);
+        let service = ElmService::new("/project", "src/Main.elm").with_registry(registry.clone());
+        for _ in 0..2 {
+            service
+                .dependency_modules("elm/core", "1.0.5")
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            registry
+                .docs_fetches
+                .load(std::sync::atomic::Ordering::SeqCst),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn stdlib_cache_is_shared_between_sessions() {
+        let registry = StubRegistry::with_packages(&[]);
+        let stdlib = StdlibCache::default();
+        for _ in 0..2 {
+            ElmService::new("/project", "src/Main.elm")
+                .with_registry(registry.clone())
+                .with_stdlib_cache(stdlib.clone())
+                .stdlib_docs()
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            registry
+                .docs_fetches
+                .load(std::sync::atomic::Ordering::SeqCst),
+            STDLIB_PACKAGES.len()
+        );
+    }
+
+    #[tokio::test]
+    async fn search_packages_maps_registry_errors() {
+        let service =
+            ElmService::new("/project", "src/Main.elm").with_registry(StubRegistry::failing());
+        let err = service.search_packages(search("json")).await.unwrap_err();
+        assert_eq!(err.message, "PACKAGES_FETCH_FAIL");
+        assert_eq!(
+            err.data,
+            Some(serde_json::json!({ "code": "PACKAGES_FETCH_FAIL" }))
+        );
+    }
+
+    #[tokio::test]
+    async fn search_packages_filters_by_license() {
+        let service = ElmService::new("/project", "src/Main.elm")
+            .with_registry(StubRegistry::with_packages(&["elm/json"]));
+        let result = service
+            .search_packages(Parameters(SearchRequest {
+                query: "json".to_string(),
+                license: Some("mit".to_string()),
+            }))
+            .await
+            .unwrap();
+        assert_eq!(result.content[0].as_text().unwrap().text, "[]");
+        assert!(result.content[1].as_text().unwrap().text.starts_with("1 "));
+    }
+
+    #[tokio::test]
+    async fn release_history_is_chronological() {
+        let service = ElmService::new("/project", "src/Main.elm")
+            .with_registry(StubRegistry::with_packages(&[]));
+        let result = service
+            .get_release_history(elm_json_request())
+            .await
+            .unwrap();
+        assert_eq!(
+            texts(&result)[0],
+            r#"[{"version":"1.0.0","published_at":"2017-07-14T02:40:00+00:00"},{"version":"1.1.0","published_at":"2020-09-13T12:26:40+00:00"}]"#
+        );
+    }
+
+    #[tokio::test]
+    async fn top_packages_prefers_exact_matches() {
+        let service = ElmService::new("/project", "src/Main.elm").with_registry(
+            StubRegistry::with_packages(&["NoRedInk/elm-json-decode-pipeline", "elm/json"]),
+        );
+        let result = service
+            .top_packages(Parameters(TopPackagesRequest {
+                query: "json".to_string(),
+                limit: Some(1),
+            }))
+            .await
+            .unwrap();
+        let found: Vec<serde_json::Value> =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0]["name"], "elm/json");
+        assert_eq!(found[0]["rank"], 1);
+    }
+
+    #[test]
+    fn with_tools_filters_router() {
+        let enabled = ["validate".to_string(), "add_package".to_string()];
+        let service = ElmService::new("/project", "src/Main.elm")
+            .with_tools(Some(&enabled), &["add_package".to_string()]);
+        assert!(service.tool_router.has_route("validate"));
+        assert!(!service.tool_router.has_route("add_package"));
+        assert!(!service.tool_router.has_route("clean"));
+    }
+
+    #[test]
+    fn structured_tools_declare_output_schema() {
+        let router = ElmService::tool_router();
+        let schema = |name: &str| router.map[name].attr.output_schema.clone();
+        let project_info = schema("project_info").unwrap();
+        assert!(project_info["properties"]["compiles"].is_object());
+        assert!(schema("validate_elm_json").unwrap()["properties"]["problems"].is_object());
+        for name in ["add_package", "remove_package"] {
+            assert!(schema(name).unwrap()["properties"]["installed"].is_object());
+        }
+        assert!(schema("validate").is_none());
+    }
 }
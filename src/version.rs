@@ -0,0 +1,116 @@
+use rmcp::schemars;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Major,
+    Minor,
+    Patch,
+    None,
+    Downgrade,
+}
+
+impl FromStr for Version {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<u32> = s
+            .trim()
+            .split('.')
+            .map(|part| part.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| anyhow::anyhow!("INVALID_VERSION: {s}"))?;
+        match parts[..] {
+            [major, minor, patch] => Ok(Version {
+                major,
+                minor,
+                patch,
+            }),
+            _ => Err(anyhow::anyhow!("INVALID_VERSION: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+pub fn compare(from: Version, to: Version) -> Change {
+    if to < from {
+        Change::Downgrade
+    } else if to.major != from.major {
+        Change::Major
+    } else if to.minor != from.minor {
+        Change::Minor
+    } else if to.patch != from.patch {
+        Change::Patch
+    } else {
+        Change::None
+    }
+}
+
+/// Whether `version` is within an elm.json range like `0.19.0 <= v < 0.20.0`.
+pub fn satisfies(constraint: &str, version: Version) -> anyhow::Result<bool> {
+    let invalid = || anyhow::anyhow!("INVALID_CONSTRAINT: {constraint}");
+    let parts: Vec<&str> = constraint.split_whitespace().collect();
+    let [lower, lower_op, "v", upper_op, upper] = parts[..] else {
+        return Err(invalid());
+    };
+    let (lower, upper): (Version, Version) = (lower.parse()?, upper.parse()?);
+    let above = match lower_op {
+        "<=" => lower <= version,
+        "<" => lower < version,
+        _ => return Err(invalid()),
+    };
+    let below = match upper_op {
+        "<=" => version <= upper,
+        "<" => version < upper,
+        _ => return Err(invalid()),
+    };
+    Ok(above && below)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn classifies_changes() {
+        assert_eq!(compare(v("1.0.0"), v("2.0.0")), Change::Major);
+        assert_eq!(compare(v("1.0.5"), v("1.1.0")), Change::Minor);
+        assert_eq!(compare(v("1.1.0"), v("1.1.3")), Change::Patch);
+        assert_eq!(compare(v("1.1.0"), v("1.1.0")), Change::None);
+        assert_eq!(compare(v("2.0.0"), v("1.9.9")), Change::Downgrade);
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        assert!("1.0".parse::<Version>().is_err());
+        assert!("1.0.x".parse::<Version>().is_err());
+        assert!("1.0.0-beta".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn checks_constraints() {
+        let range = "0.19.0 <= v < 0.20.0";
+        assert!(satisfies(range, v("0.19.1")).unwrap());
+        assert!(!satisfies(range, v("0.20.0")).unwrap());
+        assert!(!satisfies("1.0.0 < v <= 2.0.0", v("1.0.0")).unwrap());
+        assert!(satisfies("0.19.0", v("0.19.0")).is_err());
+    }
+}